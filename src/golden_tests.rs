@@ -0,0 +1,199 @@
+// Renders fixed World setups offscreen on the software adapter and compares them
+// against the reference images in tests/golden.
+//
+// GOLDEN_TOLERANCE overrides the allowed per-channel difference of a pixel.
+// UPDATE_GOLDEN=1 rewrites the reference images instead of comparing against them.
+// On failure the rendered frame and a diff image are written to target/golden.
+
+use std::path::PathBuf;
+
+use super::{Object, RenderSettings, State, World, adapter, recorder};
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 192;
+const DEFAULT_TOLERANCE: u8 = 2;
+
+struct Comparison {
+    mismatched_pixels: usize,
+    diff: image::RgbaImage,
+}
+
+fn tolerance() -> u8 {
+    std::env::var("GOLDEN_TOLERANCE")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_TOLERANCE)
+}
+
+fn compare(actual: &image::RgbaImage, expected: &image::RgbaImage, tolerance: u8) -> Comparison {
+    assert_eq!(actual.dimensions(), expected.dimensions(), "Image sizes differ!");
+
+    let mut mismatched_pixels = 0;
+    let diff = image::RgbaImage::from_fn(actual.width(), actual.height(), |x, y| {
+        let a = actual.get_pixel(x, y);
+        let b = expected.get_pixel(x, y);
+        let matches = a.0.iter()
+            .zip(b.0.iter())
+            .all(|(a, b)| a.abs_diff(*b) <= tolerance);
+
+        // Mismatches in red, everything else as a faded copy of the expected image
+        if matches {
+            let [r, g, b, _] = b.0;
+            image::Rgba([r / 4, g / 4, b / 4, 255])
+        } else {
+            mismatched_pixels += 1;
+            image::Rgba([255, 0, 0, 255])
+        }
+    });
+
+    Comparison { mismatched_pixels, diff }
+}
+
+fn settings(sample_count: u32) -> RenderSettings {
+    RenderSettings {
+        adapter: adapter::Options::fallback(),
+        sample_count,
+        immediates: true,
+        bindless: true,
+    }
+}
+
+fn render(world: &World, settings: &RenderSettings, wireframe: bool) -> image::RgbaImage {
+    let mut state = pollster::block_on(State::new_headless(WIDTH, HEIGHT, settings)).unwrap();
+    state.wireframe = wireframe;
+    state.build_ubos_for_objects(world.tris.len() + world.quads.len()).unwrap();
+    state.render(&world.quads, &world.tris, 1.0).unwrap();
+
+    image::RgbaImage::from_raw(WIDTH, HEIGHT, state.read_pixels().unwrap().unwrap()).unwrap()
+}
+
+fn check(name: &str, world: &World) {
+    check_with(name, world, &settings(1), false);
+}
+
+fn check_with(name: &str, world: &World, settings: &RenderSettings, wireframe: bool) {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let reference_path = manifest_dir.join("tests/golden").join(format!("{}.png", name));
+
+    let actual = render(world, settings, wireframe);
+
+    if std::env::var("UPDATE_GOLDEN").is_ok_and(|value| value == "1") {
+        std::fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        actual.save(&reference_path).unwrap();
+        return;
+    }
+
+    let expected = image::open(&reference_path)
+        .unwrap_or_else(|e| panic!("Can't open reference image {}: {}", reference_path.display(), e))
+        .to_rgba8();
+
+    let comparison = compare(&actual, &expected, tolerance());
+    if comparison.mismatched_pixels > 0 {
+        let output_dir = manifest_dir.join("target/golden");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let actual_path = output_dir.join(format!("{}.actual.png", name));
+        let diff_path = output_dir.join(format!("{}.diff.png", name));
+        actual.save(&actual_path).unwrap();
+        comparison.diff.save(&diff_path).unwrap();
+
+        panic!("{}: {} pixels differ from the reference, see {} and {}",
+            name, comparison.mismatched_pixels, actual_path.display(), diff_path.display());
+    }
+}
+
+#[test]
+fn compare_accepts_differences_within_tolerance() {
+    let expected = image::RgbaImage::from_pixel(4, 4, image::Rgba([100, 100, 100, 255]));
+    let actual = image::RgbaImage::from_pixel(4, 4, image::Rgba([102, 98, 100, 255]));
+
+    assert_eq!(compare(&actual, &expected, 2).mismatched_pixels, 0);
+    assert_eq!(compare(&actual, &expected, 1).mismatched_pixels, 16);
+}
+
+#[test]
+fn compare_marks_mismatched_pixels_in_diff() {
+    let expected = image::RgbaImage::from_pixel(2, 1, image::Rgba([0, 0, 0, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(1, 0, image::Rgba([0, 255, 0, 255]));
+
+    let comparison = compare(&actual, &expected, 0);
+    assert_eq!(comparison.mismatched_pixels, 1);
+    assert_eq!(comparison.diff.get_pixel(0, 0).0, [0, 0, 0, 255]);
+    assert_eq!(comparison.diff.get_pixel(1, 0).0, [255, 0, 0, 255]);
+}
+
+#[test]
+fn demo_world() {
+    check("demo_world", &World::demo());
+}
+
+#[test]
+fn demo_world_after_one_second() {
+    let mut world = World::demo();
+    world.update(1.0);
+    check("demo_world_after_one_second", &world);
+}
+
+#[test]
+fn demo_world_wireframe() {
+    check_with("demo_world_wireframe", &World::demo(), &settings(1), true);
+}
+
+#[test]
+fn demo_world_without_immediates() {
+    let settings = RenderSettings { immediates: false, ..settings(1) };
+    check_with("demo_world", &World::demo(), &settings, false);
+}
+
+#[test]
+fn demo_world_without_bindless() {
+    let settings = RenderSettings { bindless: false, ..settings(1) };
+    check_with("demo_world", &World::demo(), &settings, false);
+}
+
+#[test]
+fn demo_world_msaa() {
+    check_with("demo_world_msaa", &World::demo(), &settings(4), false);
+}
+
+#[test]
+fn depth_hides_farther_objects() {
+    // The quad is drawn first but sits in front of the triangle
+    let mut world = World::new();
+    world.quads.push(Object::new(glam::vec3(0.3, 0.0, 0.25), 0.0, glam::Vec3::ZERO));
+    world.tris.push(Object::new(glam::vec3(-0.3, 0.0, 0.75), 0.0, glam::Vec3::ZERO));
+    check("depth_hides_farther_objects", &world);
+}
+
+#[test]
+fn screenshot_matches_rendered_frame() {
+    let world = World::demo();
+    let filename = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/golden/screenshot.png");
+    std::fs::create_dir_all(filename.parent().unwrap()).unwrap();
+
+    let mut state = pollster::block_on(State::new_headless(WIDTH, HEIGHT, &settings(1))).unwrap();
+    state.build_ubos_for_objects(world.tris.len() + world.quads.len()).unwrap();
+    state.request_screenshot(&filename);
+    state.render(&world.quads, &world.tris, 1.0).unwrap();
+
+    let screenshot = image::open(&filename).unwrap().to_rgba8();
+    assert_eq!(screenshot.into_raw(), state.read_pixels().unwrap().unwrap());
+}
+
+#[test]
+fn recording_writes_every_frame() {
+    let mut world = World::demo();
+    let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/golden/recording");
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let mut state = pollster::block_on(State::new_headless(WIDTH, HEIGHT, &settings(1))).unwrap();
+    state.build_ubos_for_objects(world.tris.len() + world.quads.len()).unwrap();
+    state.start_recording(recorder::Output::PngSequence(directory.clone()), 3, 30);
+    while let Some(timestep) = state.recording_timestep() {
+        world.update(timestep);
+        state.render(&world.quads, &world.tris, 1.0).unwrap();
+    }
+    state.stop_recording();
+
+    assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 3);
+}
@@ -1,11 +1,32 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use glfw::{Action, Key, fail_on_errors, ClientApiHint};
 
 mod renderer_backend;
 mod model;
+#[cfg(test)]
+mod golden_tests;
+
+use renderer_backend::{adapter, assets, bind_group_layout::Layout, bindless, pipeline, material::Material, reflection::Reflection, shader, mesh_builder, depth_buffer::DepthBuffer, error::{BindGroupError, Error, PipelineError}, hot_reload::ShaderWatcher, immediates::{self, Immediate}, multisample::{self, MultisampleTarget}, offscreen::Offscreen, pipeline_cache::PipelineCache, readback::Readback, recorder::{self, Recorder}, resource_cache::ResourceCache, ubo::UBO};
 
-use renderer_backend::{pipeline, bind_group_layout, material::Material, mesh_builder, ubo::UBO};
+use model::{game_objects::Object, timestep::FixedTimestep};
+
+// Per-draw data, ObjectData in shaders/common.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ObjectData {
+    model: glam::Mat4,
+    // Index into the bindless material array, unused with per-material bind groups
+    material: u32,
+    padding: [u32; 3],
+}
 
-use model::game_objects::Object;
+const OBJECT: Immediate<ObjectData> = Immediate::new(0);
+
+// Material indices for the bindless path
+const QUAD_MATERIAL: u32 = 0;
+const TRIANGLE_MATERIAL: u32 = 1;
 
 struct World {
     quads: Vec<Object>,
@@ -18,16 +39,33 @@ impl World {
         World { quads: Vec::new(), tris: Vec::new() }
     }
 
+    fn demo() -> Self {
+        let mut world = World::new();
+        world.tris.push(Object::new(
+            glam::Vec3::new(-0.5, 0.0, 0.0),
+            0.0,
+            glam::vec3(0.1, 0.06, 0.0)));
+        world.quads.push(Object::new(
+            glam::Vec3::new(0.9, 0.0, 0.0),
+            0.0,
+            glam::vec3(0.0, 0.0, 0.0)));
+
+        world
+    }
+
     fn update(&mut self, dt: f32) {
 
         let update_obj = |obj: &mut Object| {
-            obj.angle = obj.angle + Self::ROTATION_SPEED * dt;
+            obj.previous_position = obj.position;
+            obj.previous_angle = obj.angle;
+
+            obj.angle += Self::ROTATION_SPEED * dt;
             if obj.angle > 360.0 {
                 obj.angle -= 360.0;
             }
 
             if !obj.velocity.cmpeq(glam::Vec3::ZERO).all() {
-                obj.position = obj.position + obj.velocity * dt;
+                obj.position += obj.velocity * dt;
 
                 if obj.position.cmpge(glam::Vec3::ONE).any() ||
                     obj.position.cmple(glam::Vec3::NEG_ONE).any() {
@@ -46,69 +84,87 @@ impl World {
     }
 }
 
+struct RenderSettings {
+    adapter: adapter::Options,
+    // MSAA samples per pixel, lowered to what the adapter supports
+    sample_count: u32,
+    // Per-draw data as immediates if the adapter has them, uniform buffers otherwise
+    immediates: bool,
+    // One bind group for every material if the adapter has texture binding arrays
+    bindless: bool,
+}
+
+enum Target<'a> {
+    Window {
+        window: &'a mut glfw::Window,
+        surface: wgpu::Surface<'a>,
+    },
+    Offscreen(Offscreen),
+}
+
 struct State<'a> {
     instance: wgpu::Instance,
-    surface: wgpu::Surface<'a>,
+    target: Target<'a>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     size: (i32, i32),
-    window: &'a mut glfw::Window,
     render_pipeline: wgpu::RenderPipeline,
+    wireframe_pipeline: wgpu::RenderPipeline,
+    wireframe: bool,
     triangle_mesh: wgpu::Buffer,
     quad_mesh: mesh_builder::Mesh,
     triangle_material: Material,
     quad_material: Material,
+    // Both materials in one bind group for the bindless path
+    bindless_materials: Option<wgpu::BindGroup>,
+    depth_buffer: DepthBuffer,
+    sample_count: u32,
+    msaa_target: Option<MultisampleTarget>,
+    // Kept around to rebuild the pipelines when the shader changes
+    material_bind_group_layout: Layout,
+    ubo_bind_group_layout: Layout,
+    shader_watcher: ShaderWatcher,
+    pipeline_cache: Option<PipelineCache>,
+    // Shared layouts and samplers
+    resources: ResourceCache,
     ubo: Option<UBO>,
+    // This frame's per-draw data for the immediates path
+    objects: Vec<ObjectData>,
+    screenshot: Option<PathBuf>,
+    recorder: Option<Recorder>,
 }
 
 impl<'a> State<'a> {
-    async fn new(window: &'a mut glfw::Window) -> Self {
+    const SHADER: &'static str = "shaders/shader.wgsl";
 
-        let size = window.get_framebuffer_size();
+    async fn new(window: &'a mut glfw::Window, settings: &RenderSettings) -> Result<Self, Error> {
 
-        let instance_descriptor = wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(), ..Default::default()
-        };
+        let size = window.get_framebuffer_size();
 
-        let instance = wgpu::Instance::new(&instance_descriptor);
+        let instance = Self::create_instance();
         let surface = instance.create_surface(window.render_context()).unwrap();
 
-        let adapter_descriptor = wgpu::RequestAdapterOptionsBase {
-            power_preference: wgpu::PowerPreference::default(),
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
-        };
-        let adapter = instance.request_adapter(&adapter_descriptor)
-            .await.unwrap();
-
-        #[cfg(debug_assertions)]
-        {
-            let backend = adapter.get_info().backend;
-            println!("Using backend: {:?}", backend);
-        }
-
-        let device_descriptor = wgpu::DeviceDescriptor {
-            required_features: wgpu::Features::empty(),
-            required_limits: wgpu::Limits::default(),
-            label: Some("Device"),
-            ..Default::default()
-        };
-        let (device, queue) = adapter
-            .request_device(&device_descriptor)
-            .await.unwrap();
+        let adapter = adapter::select(&instance, &settings.adapter, Some(&surface)).await
+            .map_err(Error::Adapter)?;
 
+        let (device, queue) = Self::request_device(&adapter, settings).await;
 
         let surface_capabilities = surface.get_capabilities(&adapter);
         let surface_format = surface_capabilities
             .formats
             .iter()
             .copied()
-            .filter(|f | f.is_srgb())
-            .next()
+            .find(|f | f.is_srgb())
             .unwrap_or(surface_capabilities.formats[0]);
+        // Screenshots copy straight out of the swapchain image when the surface allows it
+        let usage = if surface_capabilities.usages.contains(wgpu::TextureUsages::COPY_SRC) {
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        };
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage,
             format: surface_format,
             width: size.0 as u32,
             height: size.1 as u32,
@@ -118,86 +174,357 @@ impl<'a> State<'a> {
             desired_maximum_frame_latency: 2
         };
         surface.configure(&device, &config);
-        
-        let triangle_buffer = mesh_builder::make_triangle(&device);
 
-        let quad_mesh = mesh_builder::make_quad(&device);
+        let sample_count = multisample::supported_sample_count(&adapter, config.format, DepthBuffer::FORMAT, settings.sample_count);
+
+        let pipeline_cache = PipelineCache::load(&device, &adapter.get_info());
+
+        Self::from_target(instance, Target::Window { window, surface }, device, queue, config, sample_count, pipeline_cache)
+    }
 
-        let material_bind_group_layout = {
-            let mut builder = bind_group_layout::Builder::new(&device);
-            builder.add_material();
-            builder.build("Material Bind Group Layout")
+    async fn new_headless(width: u32, height: u32, settings: &RenderSettings) -> Result<Self, Error> {
+
+        let instance = Self::create_instance();
+
+        // Prefer a real GPU, but fall back to the software adapter on machines without one
+        let adapter = match adapter::select(&instance, &settings.adapter, None).await {
+            Ok(adapter) => adapter,
+            Err(_) if settings.adapter.selector.is_none() => {
+                adapter::select(&instance, &adapter::Options::fallback(), None).await
+                    .map_err(Error::Adapter)?
+            }
+            Err(e) => return Err(Error::Adapter(e)),
         };
 
-        let ubo_bind_group_layout = {
-            let mut builder = bind_group_layout::Builder::new(&device);
-            builder.add_ubo();
-            builder.build("UBO Bind Group Layout")
+        let (device, queue) = Self::request_device(&adapter, settings).await;
+
+        // Never used to configure a surface, it only keeps the size and format in one place
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: Offscreen::FORMAT,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2
         };
+        let offscreen = Offscreen::new(&device, width, height, "Offscreen Target");
+
+        let sample_count = multisample::supported_sample_count(&adapter, config.format, DepthBuffer::FORMAT, settings.sample_count);
+
+        // No pipeline cache, tests and CI renders shouldn't write to the user's cache directory
+        Self::from_target(instance, Target::Offscreen(offscreen), device, queue, config, sample_count, None)
+    }
 
-        let render_pipeline = {
-            let mut builder = pipeline::Builder::new(&device);
-            builder.set_shader_module("shaders/shader.wgsl", "vs_main", "fs_main")
-            .set_pixel_format(config.format)
-            .add_vertex_buffer_layout(mesh_builder::Vertex::get_layout())
-            .add_bind_group_layout(&material_bind_group_layout)
-            .add_bind_group_layout(&ubo_bind_group_layout);
-            builder.build("Render Pipeline")
+    fn create_instance() -> wgpu::Instance {
+        let instance_descriptor = wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(), ..Default::default()
         };
-        let triangle_materail = Material::new("../img/winry.jpg", &device, &queue, "Triangle Material", &material_bind_group_layout);
-        let quad_materail = Material::new("../img/satin.jpg", &device, &queue, "Quad Material", &material_bind_group_layout);
 
-        Self {
+        wgpu::Instance::new(&instance_descriptor)
+    }
+
+    async fn request_device(adapter: &wgpu::Adapter, settings: &RenderSettings) -> (wgpu::Device, wgpu::Queue) {
+
+        // Optional features, builders check for them and fall back when they're missing
+        let mut optional_features = wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::POLYGON_MODE_POINT |
+            wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES | wgpu::Features::PIPELINE_CACHE;
+        if settings.immediates && immediates::available(adapter) {
+            optional_features |= wgpu::Features::IMMEDIATES;
+        }
+        if settings.bindless && bindless::available(adapter) {
+            optional_features |= wgpu::Features::TEXTURE_BINDING_ARRAY;
+        }
+        let required_features = adapter.features() & optional_features;
+
+        // Both default to 0, which would leave the features unusable
+        let adapter_limit = |feature, limit| if required_features.contains(feature) { limit } else { 0 };
+        let required_limits = wgpu::Limits {
+            max_immediate_size: adapter_limit(wgpu::Features::IMMEDIATES, adapter.limits().max_immediate_size),
+            max_binding_array_elements_per_shader_stage: adapter_limit(wgpu::Features::TEXTURE_BINDING_ARRAY,
+                adapter.limits().max_binding_array_elements_per_shader_stage),
+            ..Default::default()
+        };
+
+        let device_descriptor = wgpu::DeviceDescriptor {
+            required_features,
+            required_limits,
+            label: Some("Device"),
+            ..Default::default()
+        };
+        adapter
+            .request_device(&device_descriptor)
+            .await.unwrap()
+    }
+
+    fn from_target(instance: wgpu::Instance, target: Target<'a>, device: wgpu::Device, queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration, sample_count: u32, pipeline_cache: Option<PipelineCache>) -> Result<Self, Error> {
+
+        let size = (config.width as i32, config.height as i32);
+
+        let triangle_buffer = mesh_builder::make_triangle(&device);
+
+        let quad_mesh = mesh_builder::make_quad(&device);
+
+        let mut resources = ResourceCache::new();
+        let (material_bind_group_layout, ubo_bind_group_layout) = Self::reflect_layouts(&device, &mut resources)?;
+
+        let (render_pipeline, wireframe_pipeline) = Self::build_pipelines(&device, config.format, sample_count,
+            &material_bind_group_layout, &ubo_bind_group_layout, pipeline_cache.as_ref())?;
+        Self::save_pipeline_cache(pipeline_cache.as_ref());
+        let mut shader_watcher = ShaderWatcher::new(assets::locator());
+        shader_watcher.watch(Self::SHADER);
+
+        // Bindless materials don't get their own bind groups
+        let material_layout = (!bindless::supported(&device)).then_some(&material_bind_group_layout);
+        let triangle_materail = Material::new("img/winry.jpg", &device, &queue, "Triangle Material", material_layout, &mut resources)?;
+        let quad_materail = Material::new("img/satin.jpg", &device, &queue, "Quad Material", material_layout, &mut resources)?;
+        let bindless_materials = bindless::supported(&device).then(|| {
+            let mut materials = [&quad_materail; 2];
+            materials[TRIANGLE_MATERIAL as usize] = &triangle_materail;
+            bindless::build_bind_group(&device, &material_bind_group_layout, &materials, "Bindless Materials")
+        }).transpose()?;
+
+        let depth_buffer = DepthBuffer::new(&device, config.width, config.height, DepthBuffer::FORMAT, sample_count, "Depth Buffer");
+        let msaa_target = (sample_count > 1).then(|| {
+            MultisampleTarget::new(&device, config.width, config.height, config.format, sample_count, "MSAA Target")
+        });
+
+        Ok(Self {
             instance,
-            window,
-            surface,
+            target,
             device,
             queue,
             config,
             size,
             render_pipeline,
+            wireframe_pipeline,
+            wireframe: false,
             triangle_mesh: triangle_buffer,
             quad_mesh,
             triangle_material: triangle_materail,
             quad_material: quad_materail,
+            bindless_materials,
+            depth_buffer,
+            sample_count,
+            msaa_target,
+            material_bind_group_layout,
+            ubo_bind_group_layout,
+            shader_watcher,
+            pipeline_cache,
+            resources,
             ubo: None,
+            objects: Vec::new(),
+            screenshot: None,
+            recorder: None,
+        })
+    }
+
+    // The layouts come from the shader itself, so they can't drift out of sync with it.
+    // The UBO layout is empty when the model matrix is an immediate.
+    fn reflect_layouts(device: &wgpu::Device, resources: &mut ResourceCache)
+        -> Result<(Layout, Layout), PipelineError> {
+
+        let reflection = Reflection::new(&shader::parse(Self::SHADER, &Self::shader_defines(device))?)?;
+        let material_bind_group_layout = resources.bind_group_layout(device, reflection.bind_group_layout_entries(0),
+            "Material Bind Group Layout");
+        let ubo_bind_group_layout = resources.bind_group_layout(device, reflection.bind_group_layout_entries(1),
+            "UBO Bind Group Layout");
+
+        Ok((material_bind_group_layout, ubo_bind_group_layout))
+    }
+
+    fn shader_defines(device: &wgpu::Device) -> Vec<String> {
+        let mut defines = Vec::new();
+        if immediates::supported(device) {
+            defines.push("IMMEDIATES".to_string());
+        }
+        if bindless::supported(device) {
+            defines.push("BINDLESS".to_string());
         }
+
+        defines
     }
 
-    fn render(&mut self, quads: &Vec<Object>, tris: &Vec<Object>) -> Result<(), wgpu::SurfaceError> {
+    fn build_pipelines(device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32,
+        material_bind_group_layout: &Layout, ubo_bind_group_layout: &Layout,
+        pipeline_cache: Option<&PipelineCache>) -> Result<(wgpu::RenderPipeline, wgpu::RenderPipeline), PipelineError> {
 
-        //self.device.poll(wgpu::Maintain::Wait);
+        let use_immediates = immediates::supported(device);
+        // Without immediates the per-draw data comes from a bind group at group 1
+        let per_draw_layouts: &[&Layout] = if use_immediates { &[] } else { &[ubo_bind_group_layout] };
 
-        // Upload
-        let mut offset: u64 = 0;
-        for (i, value) in quads.iter().enumerate() {
-            // Be careful here, glam uses column major matrix， ABv, B applies first, then A. So rotation first , then translation.
-            let matrix = glam::Mat4::from_translation(value.position) *
-                glam::Mat4::from_axis_angle(glam::Vec3::new(0.0, 0.0, 1.0), value.angle.to_radians());
-            self.ubo.as_mut().unwrap().upload(offset + i as u64, &matrix, &self.queue);
+        let mut builder = pipeline::Builder::new(device);
+        builder.set_shader_module(Self::SHADER, "vs_main", "fs_main")
+        .set_cache(pipeline_cache.map(|pipeline_cache| &pipeline_cache.cache));
+        for define in Self::shader_defines(device) {
+            builder.add_define(&define);
         }
+        builder.set_pixel_format(format)
+        .set_blend_mode(pipeline::BlendMode::Alpha)
+        .set_depth_format(DepthBuffer::FORMAT)
+        // Everything sits at z = 0 for now, so equal depths have to pass for later draws to show up
+        .set_depth_compare(wgpu::CompareFunction::LessEqual)
+        .set_sample_count(sample_count)
+        .set_immediate_size(if use_immediates { OBJECT.end() } else { 0 })
+        .add_vertex_buffer_layout(mesh_builder::Vertex::get_layout())
+        .add_bind_group_layout(material_bind_group_layout);
+        for layout in per_draw_layouts {
+            builder.add_bind_group_layout(layout);
+        }
+        let render_pipeline = builder.build("Render Pipeline")?;
+
+        builder.set_polygon_mode(wgpu::PolygonMode::Line)
+        .set_cull_mode(None)
+        .add_vertex_buffer_layout(mesh_builder::Vertex::get_layout())
+        .add_bind_group_layout(material_bind_group_layout);
+        for layout in per_draw_layouts {
+            builder.add_bind_group_layout(layout);
+        }
+        let wireframe_pipeline = builder.build("Wireframe Pipeline")?;
+
+        Ok((render_pipeline, wireframe_pipeline))
+    }
 
-        offset = quads.len() as u64;
-        for (i, value) in tris.iter().enumerate() {
-            // Be careful here, glam uses column major matrix， ABv, B applies first, then A. So rotation first , then translation.
-            let matrix = glam::Mat4::from_translation(value.position) *
-                glam::Mat4::from_axis_angle(glam::Vec3::new(0.0, 0.0, 1.0), value.angle.to_radians());
-            self.ubo.as_mut().unwrap().upload(offset + i as u64, &matrix, &self.queue);
+    // A failed save only costs the next startup some time.
+    fn save_pipeline_cache(pipeline_cache: Option<&PipelineCache>) {
+        if let Some(pipeline_cache) = pipeline_cache
+            && let Err(e) = pipeline_cache.save() {
+            eprintln!("Can't save the pipeline cache to {}: {}", pipeline_cache.path().display(), e);
         }
+    }
 
-        let drawable = self.surface.get_current_texture()?;
-        let image_view_descriptor = wgpu::TextureViewDescriptor::default();
-        let image_view = drawable.texture.create_view(&image_view_descriptor);
+    // Rebuilds the pipelines after the shader file changed. If it doesn't compile the
+    // last working pipelines stay in use.
+    fn reload_shaders(&mut self) {
+        if !self.shader_watcher.changed() {
+            return;
+        }
+
+        // Equal bindings give back the cached layouts, anything else would need new bind groups
+        let layouts = Self::reflect_layouts(&self.device, &mut self.resources);
+        if let Ok((material_layout, ubo_layout)) = &layouts
+            && (*material_layout != self.material_bind_group_layout || *ubo_layout != self.ubo_bind_group_layout) {
+            eprintln!("The bindings in {} changed, restart to use them\nKeeping the last working pipelines", Self::SHADER);
+            return;
+        }
+
+        match layouts.and_then(|_| Self::build_pipelines(&self.device, self.config.format, self.sample_count,
+            &self.material_bind_group_layout, &self.ubo_bind_group_layout, self.pipeline_cache.as_ref())) {
+            Ok((render_pipeline, wireframe_pipeline)) => {
+                self.render_pipeline = render_pipeline;
+                self.wireframe_pipeline = wireframe_pipeline;
+                Self::save_pipeline_cache(self.pipeline_cache.as_ref());
+                println!("Reloaded {}", Self::SHADER);
+            }
+            Err(e) => eprintln!("{}\nKeeping the last working pipelines", e),
+        }
+    }
+
+    fn window(&mut self) -> &mut glfw::Window {
+        match &mut self.target {
+            Target::Window { window, .. } => window,
+            Target::Offscreen(_) => panic!("Headless state has no window!"),
+        }
+    }
+
+    // alpha blends every object between its previous and current simulation step.
+    fn render(&mut self, quads: &[Object], tris: &[Object], alpha: f32) -> Result<(), wgpu::SurfaceError> {
+
+        //self.device.poll(wgpu::Maintain::Wait);
+
+        // Upload
+        let object_data = |material| move |value: &Object| ObjectData {
+            model: value.model_matrix(alpha),
+            material,
+            padding: [0; 3],
+        };
+        self.objects.clear();
+        self.objects.extend(quads.iter().map(object_data(QUAD_MATERIAL)));
+        self.objects.extend(tris.iter().map(object_data(TRIANGLE_MATERIAL)));
+        if let Some(ubo) = &mut self.ubo {
+            for (i, object) in self.objects.iter().enumerate() {
+                ubo.upload(i as u64, object, &self.queue);
+            }
+        }
+
+        let screenshot = self.screenshot.take();
+        let mut recorder = self.recorder.take();
 
         let command_encoder_descriptor = wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
         };
         let mut command_encoder = self.device.create_command_encoder(&command_encoder_descriptor);
 
+        match &self.target {
+            Target::Window { surface, .. } => {
+                let drawable = surface.get_current_texture()?;
+                let image_view_descriptor = wgpu::TextureViewDescriptor::default();
+                let image_view = drawable.texture.create_view(&image_view_descriptor);
+
+                self.record_render_pass(&mut command_encoder, &image_view, quads.len(), tris.len());
+                let readback = screenshot.as_ref().and_then(|_| {
+                    self.capture_frame(&mut command_encoder, &drawable.texture, quads.len(), tris.len())
+                });
+                let recorded = recorder.as_mut().filter(|recorder| recorder.is_capturing()).map(|recorder| {
+                    let readback = recorder.next_readback(&self.device);
+                    self.copy_frame(&mut command_encoder, &drawable.texture, quads.len(), tris.len(), &readback);
+                    readback
+                });
+                self.queue.submit(std::iter::once(command_encoder.finish()));
+
+                drawable.present();
+
+                if let (Some(recorder), Some(readback)) = (recorder.as_mut(), recorded) {
+                    recorder.submitted(readback);
+                }
+                if let (Some(filename), Some(readback)) = (screenshot, readback) {
+                    self.save_pixels(readback.read(&self.device), &filename);
+                }
+            }
+            Target::Offscreen(offscreen) => {
+                self.record_render_pass(&mut command_encoder, &offscreen.view, quads.len(), tris.len());
+                let recorded = recorder.as_mut().filter(|recorder| recorder.is_capturing()).map(|recorder| {
+                    let readback = recorder.next_readback(&self.device);
+                    readback.copy_from(&mut command_encoder, &offscreen.texture);
+                    readback
+                });
+                self.queue.submit(std::iter::once(command_encoder.finish()));
+
+                if let (Some(recorder), Some(readback)) = (recorder.as_mut(), recorded) {
+                    recorder.submitted(readback);
+                }
+                if let Some(filename) = screenshot {
+                    self.save_pixels(offscreen.read(&self.device, &self.queue), &filename);
+                }
+            }
+        }
+
+        self.recorder = recorder;
+        if let Some(recorder) = &mut self.recorder {
+            recorder.poll(&self.device);
+            if recorder.is_done() {
+                self.stop_recording();
+            }
+        }
+
+        Ok(())
+
+    }
+
+    fn record_render_pass(&self, command_encoder: &mut wgpu::CommandEncoder, image_view: &wgpu::TextureView, quad_count: usize, tri_count: usize) {
+
+        // With MSAA the samples only live until they're resolved into the image
+        let (view, resolve_target, store) = match &self.msaa_target {
+            Some(msaa_target) => (&msaa_target.view, Some(image_view), wgpu::StoreOp::Discard),
+            None => (image_view, None, wgpu::StoreOp::Store),
+        };
+
         let color_attachment = wgpu::RenderPassColorAttachment {
-            view: &image_view,
+            view,
             depth_slice: None,
-            resolve_target: None,
+            resolve_target,
             ops: wgpu::Operations {
                 load: wgpu::LoadOp::Clear(wgpu::Color {
                     r: 0.75,
@@ -205,14 +532,14 @@ impl<'a> State<'a> {
                     b: 0.25,
                     a: 1.0
                 }),
-                store: wgpu::StoreOp::Store,
+                store,
             },
         };
 
         let render_pass_descriptor = wgpu::RenderPassDescriptor {
             label: Some("Renderpass"),
             color_attachments: &[Some(color_attachment)],
-            depth_stencil_attachment: None,
+            depth_stencil_attachment: Some(self.depth_buffer.attachment()),
             occlusion_query_set: None,
             timestamp_writes: None,
             ..Default::default()
@@ -220,69 +547,272 @@ impl<'a> State<'a> {
 
         {
             let mut renderpass = command_encoder.begin_render_pass(&render_pass_descriptor);
-            renderpass.set_pipeline(&self.render_pipeline);
+            if self.wireframe {
+                renderpass.set_pipeline(&self.wireframe_pipeline);
+            } else {
+                renderpass.set_pipeline(&self.render_pipeline);
+            }
 
-            renderpass.set_bind_group(0, &self.quad_material.bind_group, &[]);
+            // One bind group for the whole scene if the materials are bindless
+            if let Some(bindless_materials) = &self.bindless_materials {
+                renderpass.set_bind_group(0, bindless_materials, &[]);
+            }
+            if let Some(bind_group) = &self.quad_material.bind_group {
+                renderpass.set_bind_group(0, bind_group, &[]);
+            }
             renderpass.set_vertex_buffer(0, self.quad_mesh.buffer.slice(..self.quad_mesh.offset));
             renderpass.set_index_buffer(self.quad_mesh.buffer.slice(self.quad_mesh.offset..), wgpu::IndexFormat::Uint16);
 
             let mut offset:usize = 0;
-            for i in 0..quads.len() {
-                renderpass.set_bind_group(
-                    1,
-                    &(self.ubo.as_ref().unwrap()).bind_groups[offset + i],
-                    &[]
-                );
+            for i in 0..quad_count {
+                self.set_object_data(&mut renderpass, offset + i);
                 renderpass.draw_indexed(0..6, 0, 0..1);
             }
 
             {
-                renderpass.set_bind_group(0, &self.triangle_material.bind_group, &[]);
+                if let Some(bind_group) = &self.triangle_material.bind_group {
+                    renderpass.set_bind_group(0, bind_group, &[]);
+                }
                 renderpass.set_vertex_buffer(0, self.triangle_mesh.slice(..));
-                offset = quads.len();
-                for i in 0..tris.len() {
-                    renderpass.set_bind_group(
-                        1,
-                        &(self.ubo.as_ref().unwrap()).bind_groups[offset + i],
-                        &[]
-                    );
+                offset = quad_count;
+                for i in 0..tri_count {
+                    self.set_object_data(&mut renderpass, offset + i);
                     renderpass.draw(0..3, 0..1);
                 }
             }
         }
+    }
+
+    fn set_object_data(&self, renderpass: &mut wgpu::RenderPass, object: usize) {
+        match &self.ubo {
+            Some(ubo) => renderpass.set_bind_group(1, &ubo.bind_groups[object], &[]),
+            None => OBJECT.set(renderpass, &self.objects[object]),
+        }
+    }
+
+    // Saves the next rendered frame as a PNG.
+    fn request_screenshot(&mut self, filename: impl Into<PathBuf>) {
+        self.screenshot = Some(filename.into());
+    }
+
+    fn capture_frame(&self, command_encoder: &mut wgpu::CommandEncoder, frame: &wgpu::Texture, quad_count: usize, tri_count: usize) -> Option<Readback> {
+        if !Readback::supports(self.config.format) {
+            eprintln!("Can't take a screenshot of a {:?} surface", self.config.format);
+            return None;
+        }
 
-        self.queue.submit(std::iter::once(command_encoder.finish()));
+        let readback = Readback::new(&self.device, self.config.width, self.config.height, self.config.format);
+        self.copy_frame(command_encoder, frame, quad_count, tri_count, &readback);
 
-        drawable.present();
+        Some(readback)
+    }
 
-        Ok(())
+    fn copy_frame(&self, command_encoder: &mut wgpu::CommandEncoder, frame: &wgpu::Texture, quad_count: usize, tri_count: usize, readback: &Readback) {
+        if self.config.usage.contains(wgpu::TextureUsages::COPY_SRC) {
+            readback.copy_from(command_encoder, frame);
+        } else {
+            // The swapchain image can't be copied from, so draw the frame again into a texture that can
+            let texture_descriptor = wgpu::TextureDescriptor {
+                size: frame.size(),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.config.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                label: Some("Screenshot Target"),
+                view_formats: &[],
+            };
+            let texture = self.device.create_texture(&texture_descriptor);
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            self.record_render_pass(command_encoder, &view, quad_count, tri_count);
+            readback.copy_from(command_encoder, &texture);
+        }
+    }
+
+    // Records the next frame_count frames, see recording_timestep for keeping the simulation in step.
+    fn start_recording(&mut self, output: recorder::Output, frame_count: u32, fps: u32) {
+        if !Readback::supports(self.config.format) {
+            eprintln!("Can't record a {:?} surface", self.config.format);
+            return;
+        }
 
+        self.stop_recording();
+        self.recorder = Some(Recorder::new(output, frame_count, fps, self.config.width, self.config.height, self.config.format));
+    }
+
+    // Fixed simulation step while frames are being recorded, so the clip doesn't depend on render speed.
+    fn recording_timestep(&self) -> Option<f32> {
+        self.recorder.as_ref()
+            .filter(|recorder| recorder.is_capturing())
+            .map(|recorder| recorder.timestep())
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            match recorder.finish(&self.device) {
+                Ok(path) => println!("Saved recording to {}", path.display()),
+                Err(e) => eprintln!("Can't save recording: {}", e),
+            }
+        }
+    }
+
+    fn save_pixels(&self, pixels: Result<Vec<u8>, wgpu::BufferAsyncError>, filename: &Path) {
+        let pixels = match pixels {
+            Ok(pixels) => pixels,
+            Err(e) => return eprintln!("Can't save screenshot to {}: {}", filename.display(), e),
+        };
+        match image::save_buffer(filename, &pixels, self.config.width, self.config.height, image::ExtendedColorType::Rgba8) {
+            Ok(_) => println!("Saved screenshot to {}", filename.display()),
+            Err(e) => eprintln!("Can't save screenshot to {}: {}", filename.display(), e),
+        }
+    }
+
+    // Only available in headless mode, returns the last rendered frame as tightly packed RGBA8.
+    fn read_pixels(&self) -> Option<Result<Vec<u8>, wgpu::BufferAsyncError>> {
+        match &self.target {
+            Target::Window { .. } => None,
+            Target::Offscreen(offscreen) => Some(offscreen.read(&self.device, &self.queue)),
+        }
     }
 
     fn resize(&mut self, new_size: (i32, i32)) {
         if new_size.0 > 0 && new_size.1 > 0 {
+            // Frames of a recording all need the same size
+            if self.recorder.as_ref().is_some_and(|recorder| recorder.size() != (new_size.0 as u32, new_size.1 as u32)) {
+                eprintln!("Size changed, stopping the recording early");
+                self.stop_recording();
+            }
             self.size = new_size;
             self.config.width = new_size.0 as u32;
             self.config.height = new_size.1 as u32;
-            self.surface.configure(&self.device, &self.config);
+            self.depth_buffer = DepthBuffer::new(&self.device, self.config.width, self.config.height, DepthBuffer::FORMAT, self.sample_count, "Depth Buffer");
+            if self.msaa_target.is_some() {
+                self.msaa_target = Some(MultisampleTarget::new(&self.device, self.config.width, self.config.height, self.config.format, self.sample_count, "MSAA Target"));
+            }
+            match &mut self.target {
+                Target::Window { surface, .. } => surface.configure(&self.device, &self.config),
+                Target::Offscreen(offscreen) => {
+                    *offscreen = Offscreen::new(&self.device, self.config.width, self.config.height, "Offscreen Target");
+                }
+            }
         }
     }
 
     fn update_surface(&mut self) {
-        self.surface = self.instance.create_surface(self.window.render_context()).unwrap();
+        if let Target::Window { window, surface } = &mut self.target {
+            *surface = self.instance.create_surface(window.render_context()).unwrap();
+        }
     }
 
-    pub fn build_ubos_for_objects(&mut self, object_count: usize) {
-        let ubo_bind_group_layout = {
-            let mut builder = bind_group_layout::Builder::new(&self.device);
-            builder.add_ubo();
-            builder.build("UBO Bind Group Layout")
+    // Only needed without immediates, they carry the per-draw data otherwise.
+    pub fn build_ubos_for_objects(&mut self, object_count: usize) -> Result<(), BindGroupError> {
+        if immediates::supported(&self.device) {
+            return Ok(());
+        }
+        self.ubo = Some(UBO::new(&self.device, object_count, size_of::<ObjectData>() as u64, &self.ubo_bind_group_layout)?);
+
+        Ok(())
+    }
+}
+
+struct Options {
+    headless: Option<String>,
+    record: Option<String>,
+    frames: u32,
+    fps: u32,
+    tick_rate: f64,
+    max_steps: u32,
+    list_adapters: bool,
+    // The asset root, where shaders and images are looked for first
+    assets: Option<PathBuf>,
+    // More directories to search after the root
+    asset_paths: Vec<PathBuf>,
+    render: RenderSettings,
+}
+
+impl Options {
+    fn parse() -> Self {
+        let mut options = Options {
+            headless: None,
+            record: None,
+            frames: 120,
+            fps: 30,
+            tick_rate: 60.0,
+            max_steps: 8,
+            list_adapters: std::env::var("GIGD_LIST_ADAPTERS").is_ok_and(|value| value == "1"),
+            assets: None,
+            asset_paths: Vec::new(),
+            render: RenderSettings {
+                adapter: adapter::Options::from_env(),
+                sample_count: 4,
+                immediates: true,
+                bindless: true,
+            },
         };
-        self.ubo = Some(UBO::new(&self.device, object_count, ubo_bind_group_layout));
+
+        let mut args = std::env::args().skip(1).peekable();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                // --headless [output.png] renders without opening a window
+                "--headless" => {
+                    let filename = args.next_if(|value| !value.starts_with("--"));
+                    options.headless = Some(filename.unwrap_or("frame.png".to_string()));
+                }
+                // --record <output.gif | output.png | directory> records frames of the run loop
+                "--record" => options.record = Some(Self::value(&arg, args.next())),
+                "--frames" => options.frames = Self::value_where(&arg, args.next(), |&frames| frames > 0),
+                // APNG stores the frame delay as 1/fps seconds in 16 bits
+                "--fps" => options.fps = Self::value_where(&arg, args.next(), |&fps| fps > 0 && fps <= u16::MAX as u32),
+                // Simulation steps per second and how many of them a single frame may run
+                "--tick-rate" => {
+                    options.tick_rate = Self::value_where(&arg, args.next(), |&rate: &f64| rate.is_finite() && rate > 0.0);
+                }
+                "--max-steps" => options.max_steps = Self::value_where(&arg, args.next(), |&steps| steps >= 1),
+                // The asset root, defaults to $GIGD_ASSETS or the executable's directory
+                "--assets" => options.assets = Some(Self::value(&arg, args.next())),
+                // Can be repeated, searched after the asset root
+                "--asset-path" => options.asset_paths.push(Self::value(&arg, args.next())),
+                // Prints every adapter with its backend, features and limits
+                "--list-adapters" => options.list_adapters = true,
+                // --adapter <index | name | backend>
+                "--adapter" => {
+                    let value: String = Self::value(&arg, args.next());
+                    options.render.adapter.selector = Some(adapter::Selector::parse(&value));
+                }
+                "--fallback-adapter" => options.render.adapter.force_fallback = true,
+                // MSAA samples per pixel, 1 turns it off
+                "--msaa" => options.render.sample_count = Self::value_where(&arg, args.next(), |samples| [1, 2, 4, 8, 16].contains(samples)),
+                // Uses a uniform buffer per object even if immediates are available
+                "--no-immediates" => options.render.immediates = false,
+                // Binds each material's texture separately even if binding arrays are available
+                "--no-bindless" => options.render.bindless = false,
+                _ => {
+                    eprintln!("Unknown argument: {}", arg);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        options
+    }
+
+    fn value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T {
+        Self::value_where(flag, value, |_| true)
+    }
+
+    // Like value, but also rejects values `valid` returns false for.
+    fn value_where<T: std::str::FromStr>(flag: &str, value: Option<String>, valid: impl Fn(&T) -> bool) -> T {
+        match value.and_then(|value| value.parse().ok()).filter(valid) {
+            Some(value) => value,
+            None => {
+                eprintln!("{} needs a valid value", flag);
+                std::process::exit(1);
+            }
+        }
     }
 }
 
-async fn run() {
+async fn run(options: &Options) -> Result<(), Error> {
     let mut glfw = glfw::init(fail_on_errors!()).unwrap();
 
     glfw.window_hint(glfw::WindowHint::ClientApi(ClientApiHint::NoApi));
@@ -290,43 +820,42 @@ async fn run() {
 
     let (mut window, events) = glfw.create_window(800, 600, "It's WGPU time", glfw::WindowMode::Windowed).unwrap();
 
-    let mut state = State::new(&mut window).await;
+    let mut state = State::new(&mut window, &options.render).await?;
 
-    state.window.set_framebuffer_size_polling(true);
-    state.window.set_key_polling(true);
-    state.window.set_mouse_button_polling(true);
-    state.window.set_pos_polling(true);
+    state.window().set_framebuffer_size_polling(true);
+    state.window().set_key_polling(true);
+    state.window().set_mouse_button_polling(true);
+    state.window().set_pos_polling(true);
 
 
     // Build world
-    let mut world = World::new();
-    world.tris.push(Object {
-        position: glam::Vec3::new(-0.5, 0.0, 0.0),
-        angle: 0.0,
-        velocity: glam::vec3(0.1, 0.06, 0.0)
-    });
-    world.quads.push(Object {
-        position: glam::Vec3::new(0.9, 0.0, 0.0),
-        angle: 0.0,
-        velocity: glam::vec3(0.0, 0.0, 0.0),
-    });
-    state.build_ubos_for_objects(world.tris.len() + world.quads.len());
+    let mut world = World::demo();
+    state.build_ubos_for_objects(world.tris.len() + world.quads.len())?;
+
+    if let Some(output) = &options.record {
+        state.start_recording(recorder::Output::from_path(output), options.frames, options.fps);
+    }
 
+    let mut timestep = FixedTimestep::new(options.tick_rate, options.max_steps);
     let mut delta_time;
     let mut last_time = glfw.get_time();
 
-    while !state.window.should_close() {
+    while !state.window().should_close() {
         let current_time = glfw.get_time();
         delta_time = current_time - last_time;
         last_time = current_time;
 
-        world.update(delta_time as f32);
+        let delta_time = state.recording_timestep().map_or(delta_time, f64::from);
+        for _ in 0..timestep.advance(delta_time) {
+            world.update(timestep.tick());
+        }
 
         glfw.poll_events();
         for event in glfw::flush_messages(&events) {
             handle_window_event(&mut state, event);
         }
-        match state.render(&world.quads, &world.tris) {
+        state.reload_shaders();
+        match state.render(&world.quads, &world.tris, timestep.alpha()) {
             Ok(_) => {}
             Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
                 state.update_surface();
@@ -337,29 +866,90 @@ async fn run() {
             }
         }
     }
+
+    state.stop_recording();
+
+    Ok(())
 }
 
 fn handle_window_event(state: &mut State, (_time, event): (f64, glfw::WindowEvent)) {
     match event {
         glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
-            state.window.set_should_close(true);
+            state.window().set_should_close(true);
+        }
+
+        glfw::WindowEvent::Key(Key::F2, _, Action::Press, _) => {
+            state.wireframe = !state.wireframe;
+        }
+
+        glfw::WindowEvent::Key(Key::F12, _, Action::Press, _) => {
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+            state.request_screenshot(format!("screenshot_{}.png", timestamp));
         }
 
         glfw::WindowEvent::Pos(..) => {
             state.update_surface();
-            let new_size = state.window.get_framebuffer_size();
+            let new_size = state.window().get_framebuffer_size();
             state.resize(new_size);
         }
 
         glfw::WindowEvent::FramebufferSize(_width, _height) => {
             state.update_surface();
-            let new_size = state.window.get_framebuffer_size();
+            let new_size = state.window().get_framebuffer_size();
             state.resize(new_size);
         }
         _ => {}
     }
 }
 
+async fn run_headless(filename: &str, options: &Options) -> Result<(), Error> {
+    let mut state = State::new_headless(800, 600, &options.render).await?;
+
+    let mut world = World::demo();
+    state.build_ubos_for_objects(world.tris.len() + world.quads.len())?;
+
+    if let Some(output) = &options.record {
+        let mut timestep = FixedTimestep::new(options.tick_rate, options.max_steps);
+        state.start_recording(recorder::Output::from_path(output), options.frames, options.fps);
+        while let Some(delta_time) = state.recording_timestep() {
+            for _ in 0..timestep.advance(delta_time as f64) {
+                world.update(timestep.tick());
+            }
+            state.render(&world.quads, &world.tris, timestep.alpha()).unwrap();
+        }
+        state.stop_recording();
+        return Ok(());
+    }
+
+    state.render(&world.quads, &world.tris, 1.0).unwrap();
+    let pixels = state.read_pixels().unwrap()?;
+
+    image::save_buffer(filename, &pixels, state.config.width, state.config.height, image::ExtendedColorType::Rgba8)
+        .unwrap();
+    println!("Saved frame to {}", filename);
+
+    Ok(())
+}
+
 fn main() {
-    pollster::block_on(run());
+    let options = Options::parse();
+    assets::set_locator(assets::Locator::from_env(options.assets.clone(), &options.asset_paths));
+
+    if options.list_adapters {
+        pollster::block_on(adapter::list(&State::create_instance()));
+        return;
+    }
+
+    let result = match &options.headless {
+        Some(filename) => pollster::block_on(run_headless(filename, &options)),
+        None => pollster::block_on(run(&options)),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        if matches!(e, Error::Adapter(_)) {
+            eprintln!("Available adapters:");
+            pollster::block_on(adapter::list(&State::create_instance()));
+        }
+        std::process::exit(1);
+    }
 }
\ No newline at end of file
@@ -1,7 +1,63 @@
-
 pub struct Object {
     pub position: glam::Vec3,
     pub angle: f32, // degrees
     
     pub velocity: glam::Vec3,
-}
\ No newline at end of file
+
+    // State before the last simulation step, rendering blends between the two
+    pub previous_position: glam::Vec3,
+    pub previous_angle: f32,
+}
+
+impl Object {
+    pub fn new(position: glam::Vec3, angle: f32, velocity: glam::Vec3) -> Self {
+        Object {
+            position,
+            angle,
+            velocity,
+            previous_position: position,
+            previous_angle: angle,
+        }
+    }
+
+    // alpha = 0 is the previous step, alpha = 1 the current one.
+    pub fn model_matrix(&self, alpha: f32) -> glam::Mat4 {
+        let position = self.previous_position.lerp(self.position, alpha);
+
+        // The angle wraps at 360, so blend along the shorter way round
+        let mut delta = self.angle - self.previous_angle;
+        if delta > 180.0 {
+            delta -= 360.0;
+        } else if delta < -180.0 {
+            delta += 360.0;
+        }
+        let angle = self.previous_angle + delta * alpha;
+
+        // Be careful here, glam uses column major matrix， ABv, B applies first, then A. So rotation first , then translation.
+        glam::Mat4::from_translation(position) *
+            glam::Mat4::from_axis_angle(glam::Vec3::new(0.0, 0.0, 1.0), angle.to_radians())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Object;
+
+    #[test]
+    fn model_matrix_interpolates_across_angle_wrap() {
+        let mut object = Object::new(glam::Vec3::ZERO, 350.0, glam::Vec3::ZERO);
+        object.angle = 10.0;
+
+        let expected = glam::Mat4::from_axis_angle(glam::Vec3::Z, 0.0);
+        assert!(object.model_matrix(0.5).abs_diff_eq(expected, 1e-5));
+    }
+
+    #[test]
+    fn model_matrix_interpolates_position() {
+        let mut object = Object::new(glam::Vec3::ZERO, 0.0, glam::Vec3::ZERO);
+        object.position = glam::vec3(1.0, 0.0, 0.0);
+
+        let expected = glam::Mat4::from_translation(glam::vec3(0.25, 0.0, 0.0));
+        assert!(object.model_matrix(0.25).abs_diff_eq(expected, 1e-5));
+    }
+}
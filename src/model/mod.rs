@@ -1 +1,2 @@
-pub mod game_objects;
\ No newline at end of file
+pub mod game_objects;
+pub mod timestep;
\ No newline at end of file
@@ -0,0 +1,70 @@
+// Accumulates frame time and hands it out in fixed simulation steps.
+pub struct FixedTimestep {
+    tick: f64,
+    max_steps: u32,
+    accumulator: f64,
+}
+
+impl FixedTimestep {
+    // Absorbs rounding so that e.g. two 1/30 s frames make exactly four 1/60 s steps
+    const EPSILON: f64 = 1e-9;
+
+    pub fn new(tick_rate: f64, max_steps: u32) -> Self {
+        debug_assert!(tick_rate.is_finite() && tick_rate > 0.0, "tick rate must be positive, not {}", tick_rate);
+        debug_assert!(max_steps >= 1, "max_steps must be at least 1");
+
+        FixedTimestep {
+            tick: 1.0 / tick_rate,
+            max_steps,
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn tick(&self) -> f32 {
+        self.tick as f32
+    }
+
+    // Returns how many steps to simulate for a frame that took frame_time seconds.
+    pub fn advance(&mut self, frame_time: f64) -> u32 {
+        self.accumulator += frame_time;
+
+        let steps = ((self.accumulator + Self::EPSILON) / self.tick) as u32;
+        if steps > self.max_steps {
+            // Drop the backlog after a long hitch instead of trying to catch up
+            self.accumulator %= self.tick;
+            return self.max_steps;
+        }
+
+        self.accumulator = (self.accumulator - steps as f64 * self.tick).max(0.0);
+        steps
+    }
+
+    // How far the leftover time is into the next step, for interpolating the rendered state.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.tick) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FixedTimestep;
+
+    #[test]
+    fn advance_keeps_leftover_time() {
+        let mut timestep = FixedTimestep::new(10.0, 8);
+
+        assert_eq!(timestep.advance(0.25), 2);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-5);
+        assert_eq!(timestep.advance(0.05), 1);
+        assert!(timestep.alpha().abs() < 1e-5);
+    }
+
+    #[test]
+    fn advance_clamps_long_frames() {
+        let mut timestep = FixedTimestep::new(10.0, 4);
+
+        assert_eq!(timestep.advance(10.05), 4);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-4);
+        assert_eq!(timestep.advance(0.0), 0);
+    }
+}
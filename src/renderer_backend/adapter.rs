@@ -0,0 +1,120 @@
+pub enum Selector {
+    Index(usize),
+    Backend(wgpu::Backend),
+    Name(String),
+}
+
+impl Selector {
+    // A number picks by index in the --list-adapters order, a backend name by backend,
+    // anything else matches part of the adapter name.
+    pub fn parse(value: &str) -> Self {
+        if let Ok(index) = value.parse() {
+            return Selector::Index(index);
+        }
+
+        let backend = wgpu::Backend::ALL.iter()
+            .copied()
+            .find(|backend| backend.to_str().eq_ignore_ascii_case(value));
+        match backend {
+            Some(backend) => Selector::Backend(backend),
+            None => Selector::Name(value.to_lowercase()),
+        }
+    }
+
+    fn matches(&self, index: usize, info: &wgpu::AdapterInfo) -> bool {
+        match self {
+            Selector::Index(i) => *i == index,
+            Selector::Backend(backend) => *backend == info.backend,
+            Selector::Name(name) => info.name.to_lowercase().contains(name.as_str()),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Options {
+    pub selector: Option<Selector>,
+    pub force_fallback: bool,
+}
+
+impl Options {
+    // GIGD_ADAPTER and GIGD_FORCE_FALLBACK_ADAPTER=1 work like --adapter and --fallback-adapter.
+    pub fn from_env() -> Self {
+        Options {
+            selector: std::env::var("GIGD_ADAPTER").ok().map(|value| Selector::parse(&value)),
+            force_fallback: std::env::var("GIGD_FORCE_FALLBACK_ADAPTER").is_ok_and(|value| value == "1"),
+        }
+    }
+
+    pub fn fallback() -> Self {
+        Options {
+            selector: None,
+            force_fallback: true,
+        }
+    }
+}
+
+pub async fn select(instance: &wgpu::Instance, options: &Options, compatible_surface: Option<&wgpu::Surface<'_>>)
+    -> Result<wgpu::Adapter, String> {
+
+    let adapter = match &options.selector {
+        Some(selector) => {
+            instance.enumerate_adapters(wgpu::Backends::all()).await
+                .into_iter()
+                .enumerate()
+                .filter(|(i, adapter)| selector.matches(*i, &adapter.get_info()))
+                .map(|(_, adapter)| adapter)
+                .filter(|adapter| !options.force_fallback || adapter.get_info().device_type == wgpu::DeviceType::Cpu)
+                .find(|adapter| compatible_surface.is_none_or(|surface| adapter.is_surface_supported(surface)))
+                .ok_or("No adapter matches the selection".to_string())?
+        }
+        None => {
+            let adapter_descriptor = wgpu::RequestAdapterOptionsBase {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface,
+                force_fallback_adapter: options.force_fallback,
+            };
+            instance.request_adapter(&adapter_descriptor).await
+                .map_err(|e| e.to_string())?
+        }
+    };
+
+    #[cfg(debug_assertions)]
+    {
+        let info = adapter.get_info();
+        println!("Using adapter: {} ({}, {:?})", info.name, info.backend, info.device_type);
+    }
+
+    Ok(adapter)
+}
+
+pub fn report(index: usize, adapter: &wgpu::Adapter) -> String {
+    let info = adapter.get_info();
+    let features: Vec<&str> = adapter.features().iter_names().map(|(name, _)| name).collect();
+
+    format!("[{}] {}\n    backend: {}\n    device type: {:?}\n    driver: {} {}\n    features: {}\n    limits: {:#?}\n",
+        index, info.name, info.backend, info.device_type, info.driver, info.driver_info,
+        features.join(", "), adapter.limits())
+}
+
+pub async fn list(instance: &wgpu::Instance) {
+    let adapters = instance.enumerate_adapters(wgpu::Backends::all()).await;
+    if adapters.is_empty() {
+        println!("No adapters found");
+    }
+
+    for (i, adapter) in adapters.iter().enumerate() {
+        println!("{}", report(i, adapter));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Selector;
+
+    #[test]
+    fn selector_parses_index_backend_and_name() {
+        assert!(matches!(Selector::parse("1"), Selector::Index(1)));
+        assert!(matches!(Selector::parse("Vulkan"), Selector::Backend(wgpu::Backend::Vulkan)));
+        assert!(matches!(Selector::parse("LLVMpipe"), Selector::Name(name) if name == "llvmpipe"));
+    }
+}
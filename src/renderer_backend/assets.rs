@@ -0,0 +1,206 @@
+use std::borrow::Cow;
+use std::path::{Component, Path, PathBuf};
+use std::sync::RwLock;
+
+use super::error::AssetError;
+
+// The default shaders and images, compiled into the binary. Asset names are relative
+// to a search directory, like "shaders/shader.wgsl" or "img/winry.jpg".
+const EMBEDDED: &[(&str, &[u8])] = &[
+    ("shaders/shader.wgsl", include_bytes!("../shaders/shader.wgsl")),
+    ("shaders/common.wgsl", include_bytes!("../shaders/common.wgsl")),
+    ("img/winry.jpg", include_bytes!("../../img/winry.jpg")),
+    ("img/satin.jpg", include_bytes!("../../img/satin.jpg")),
+];
+
+// Sets the root directory, see Locator::from_env.
+pub const ROOT_VARIABLE: &str = "GIGD_ASSETS";
+// More search directories, separated like PATH. Relative ones start at the root.
+pub const SEARCH_PATH_VARIABLE: &str = "GIGD_ASSET_PATH";
+
+static LOCATOR: RwLock<Option<Locator>> = RwLock::new(None);
+
+pub struct Asset {
+    // The file on disk, or the name for an embedded asset
+    pub path: PathBuf,
+    pub bytes: Cow<'static, [u8]>,
+}
+
+impl Asset {
+    pub fn text(&self) -> Result<&str, AssetError> {
+        std::str::from_utf8(&self.bytes)
+            .map_err(|e| AssetError::Decode { path: self.path.clone(), message: e.to_string() })
+    }
+}
+
+// Finds assets in the root directory, then in the search paths, then among the embedded
+// copies. Files on disk override embedded ones, so assets can change without a rebuild.
+#[derive(Clone, Debug)]
+pub struct Locator {
+    root: PathBuf,
+    search_paths: Vec<PathBuf>,
+}
+
+impl Locator {
+
+    // Searches just `root` until search paths are added.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into(), search_paths: Vec::new() }
+    }
+
+    // The root is `root` from the command line, else $GIGD_ASSETS, else the executable's
+    // directory. `search_paths` from the command line come next, relative to the current
+    // directory, then $GIGD_ASSET_PATH. Debug builds search the crate last so edits to
+    // src/shaders show up without a rebuild.
+    pub fn from_env(root: Option<PathBuf>, search_paths: &[PathBuf]) -> Self {
+        let root = root
+            .or_else(|| std::env::var_os(ROOT_VARIABLE).filter(|value| !value.is_empty()).map(PathBuf::from))
+            .or_else(|| std::env::current_exe().ok()?.parent().map(Path::to_path_buf))
+            .unwrap_or_default();
+
+        let mut locator = Self::new(root);
+        for directory in search_paths {
+            locator.add_search_path(std::path::absolute(directory).unwrap_or_else(|_| directory.clone()));
+        }
+        if let Some(search_path) = std::env::var_os(SEARCH_PATH_VARIABLE) {
+            for directory in std::env::split_paths(&search_path) {
+                locator.add_search_path(directory);
+            }
+        }
+        if cfg!(debug_assertions) {
+            let crate_directory = Path::new(env!("CARGO_MANIFEST_DIR"));
+            locator.add_search_path(crate_directory.join("src"));
+            locator.add_search_path(crate_directory);
+        }
+
+        locator
+    }
+
+    pub fn add_search_path(&mut self, directory: impl AsRef<Path>) -> &mut Self {
+        let directory = self.root.join(directory);
+        if !self.search_paths.contains(&directory) {
+            self.search_paths.push(directory);
+        }
+
+        self
+    }
+
+    // Where `name` could be on disk, in search order, whether the files exist or not.
+    // Absolute names are only looked for where they point.
+    pub fn candidates(&self, name: impl AsRef<Path>) -> Vec<PathBuf> {
+        let name = name.as_ref();
+        if name.is_absolute() {
+            return vec![name.to_path_buf()];
+        }
+
+        std::iter::once(&self.root)
+            .chain(&self.search_paths)
+            .map(|directory| directory.join(name))
+            .collect()
+    }
+
+    pub fn find(&self, name: impl AsRef<Path>) -> Option<PathBuf> {
+        self.candidates(name).into_iter().find(|path| path.is_file())
+    }
+
+    pub fn load(&self, name: impl AsRef<Path>) -> Result<Asset, AssetError> {
+        let name = name.as_ref();
+        if let Some(path) = self.find(name) {
+            return match std::fs::read(&path) {
+                Ok(bytes) => Ok(Asset { path, bytes: Cow::Owned(bytes) }),
+                Err(source) => Err(AssetError::Read { path, source }),
+            };
+        }
+
+        let normalized = normalize(name);
+        EMBEDDED.iter()
+            .find(|(embedded, _)| Path::new(embedded) == normalized)
+            .map(|(_, bytes)| Asset { path: normalized.clone(), bytes: Cow::Borrowed(*bytes) })
+            .ok_or_else(|| AssetError::NotFound { name: name.to_path_buf(), searched: self.candidates(name) })
+    }
+}
+
+// Replaces the locator the functions below use.
+pub fn set_locator(locator: Locator) {
+    *LOCATOR.write().unwrap() = Some(locator);
+}
+
+// The current locator, Locator::from_env(None, &[]) until set_locator is called.
+pub fn locator() -> Locator {
+    LOCATOR.write().unwrap().get_or_insert_with(|| Locator::from_env(None, &[])).clone()
+}
+
+pub fn load(name: impl AsRef<Path>) -> Result<Asset, AssetError> {
+    locator().load(name)
+}
+
+// Resolves "." and ".." without touching the filesystem, so includes like
+// "shaders/../shaders/common.wgsl" find the same asset. Leading ".." stay.
+pub fn normalize(name: impl AsRef<Path>) -> PathBuf {
+    let mut path = PathBuf::new();
+    for component in name.as_ref().components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(path.components().next_back(), Some(Component::Normal(_))) => {
+                path.pop();
+            }
+            component => path.push(component),
+        }
+    }
+
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Locator, normalize};
+    use crate::renderer_backend::error::AssetError;
+
+    #[test]
+    fn disk_files_override_embedded_ones() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/assets");
+        std::fs::create_dir_all(directory.join("shaders")).unwrap();
+        std::fs::write(directory.join("shaders/shader.wgsl"), "// override").unwrap();
+        let locator = Locator::new(&directory);
+
+        let overridden = locator.load("shaders/shader.wgsl").unwrap();
+        assert_eq!(overridden.text().unwrap(), "// override");
+        let embedded = locator.load("shaders/./common.wgsl").unwrap();
+        assert_eq!(embedded.path, Path::new("shaders/common.wgsl"));
+        let image = Locator::new(directory.join("missing")).load("img/winry.jpg").unwrap();
+        assert_eq!(image.path, Path::new("img/winry.jpg"));
+    }
+
+    #[test]
+    fn search_paths_follow_the_root() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/asset_root");
+        std::fs::create_dir_all(root.join("extra")).unwrap();
+        std::fs::write(root.join("extra/only_extra.wgsl"), "").unwrap();
+        std::fs::write(root.join("extra/both.wgsl"), "").unwrap();
+        std::fs::write(root.join("both.wgsl"), "").unwrap();
+        let mut locator = Locator::new(&root);
+        locator.add_search_path("extra");
+
+        assert_eq!(locator.find("only_extra.wgsl").unwrap(), root.join("extra/only_extra.wgsl"));
+        assert_eq!(locator.find("both.wgsl").unwrap(), root.join("both.wgsl"));
+
+        let error = locator.load("missing.wgsl").err().unwrap();
+        assert!(matches!(&error, AssetError::NotFound { searched, .. } if searched.len() == 2), "{}", error);
+    }
+
+    #[test]
+    fn command_line_search_paths_start_at_the_current_directory() {
+        let locator = Locator::from_env(Some("/assets".into()), &["mods".into()]);
+        let candidates = locator.candidates("img/winry.jpg");
+        assert_eq!(candidates[0], Path::new("/assets/img/winry.jpg"));
+        assert_eq!(candidates[1], std::path::absolute("mods/img/winry.jpg").unwrap());
+    }
+
+    #[test]
+    fn normalize_keeps_leading_parent_dirs() {
+        assert_eq!(normalize("shaders/../shaders/common.wgsl"), Path::new("shaders/common.wgsl"));
+        assert_eq!(normalize("../target/./golden/a.wgsl"), Path::new("../target/golden/a.wgsl"));
+    }
+}
@@ -1,6 +1,12 @@
+use super::bind_group_layout::Layout;
+use super::error::BindGroupError;
+
+// build checks every resource against the layout entry at its binding before wgpu sees it.
 pub struct Builder<'a> {
     entries: Vec<wgpu::BindGroupEntry<'a>>,
-    layout: Option<&'a wgpu::BindGroupLayout>,
+    // The texture views of each entry, wgpu::TextureView doesn't tell how it was created
+    views: Vec<Vec<View>>,
+    layout: Option<&'a Layout>,
     device: &'a wgpu::Device,
 }
 
@@ -9,6 +15,7 @@ impl<'a> Builder<'a> {
    pub fn new(device: &'a wgpu::Device) -> Self {
         Self {
             entries: Vec::new(),
+            views: Vec::new(),
             layout: None,
             device
         }
@@ -17,53 +24,382 @@ impl<'a> Builder<'a> {
     pub fn reset(&mut self) {
         
         self.entries.clear();
+        self.views.clear();
     }
 
-    pub fn set_layout(&mut self, layout: &'a wgpu::BindGroupLayout) ->&mut Self {
+    pub fn set_layout(&mut self, layout: &'a Layout) ->&mut Self {
         
         self.layout = Some(layout);
 
         self
     }
 
-    pub fn add_material(&mut self, view: &'a wgpu::TextureView, sampler: &'a wgpu::Sampler) ->&mut Self {
-        self.entries.push(wgpu::BindGroupEntry {
-            binding: self.entries.len() as u32,
-            resource: wgpu::BindingResource::TextureView(view),
-        });
-        
-        self.entries.push(wgpu::BindGroupEntry {
-            binding: self.entries.len() as u32,
-            resource: wgpu::BindingResource::Sampler(sampler),
-        });
+    pub fn add_material(&mut self, view: &'a wgpu::TextureView, descriptor: &wgpu::TextureViewDescriptor,
+        sampler: &'a wgpu::Sampler) ->&mut Self {
+        self.add_texture_view(view, descriptor);
+        self.add_sampler(sampler)
+    }
+
+    pub fn add_buffer(&mut self, buffer: &'a wgpu::Buffer, offset: u64) ->&mut Self {
+        self.add_resource(wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer,
+            offset,
+            size: None,
+        }))
+    }
+
+    // Binds `size` bytes from `offset` instead of the rest of the buffer.
+    #[allow(dead_code)]
+    pub fn add_buffer_range(&mut self, buffer: &'a wgpu::Buffer, offset: u64, size: u64) ->&mut Self {
+        self.add_resource(wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer,
+            offset,
+            size: std::num::NonZeroU64::new(size),
+        }))
+    }
+
+    // Also covers storage textures. `descriptor` is the one the view was created with,
+    // build checks its dimension, aspect and format against the layout.
+    pub fn add_texture_view(&mut self, view: &'a wgpu::TextureView, descriptor: &wgpu::TextureViewDescriptor) ->&mut Self {
+        self.add_resource(wgpu::BindingResource::TextureView(view));
+        *self.views.last_mut().unwrap() = vec![View::new(view, descriptor)];
 
         self
     }
 
-    pub fn add_buffer(&mut self, buffer: &'a wgpu::Buffer, offset: u64) ->&mut Self {
+    // For a binding array entry, see bind_group_layout::Builder::set_count. Every view was created with `descriptor`.
+    pub fn add_texture_view_array(&mut self, views: &'a [&'a wgpu::TextureView], descriptor: &wgpu::TextureViewDescriptor) ->&mut Self {
+        self.add_resource(wgpu::BindingResource::TextureViewArray(views));
+        *self.views.last_mut().unwrap() = views.iter().map(|view| View::new(view, descriptor)).collect();
+
+        self
+    }
+
+    pub fn add_sampler(&mut self, sampler: &'a wgpu::Sampler) ->&mut Self {
+        self.add_resource(wgpu::BindingResource::Sampler(sampler))
+    }
+
+    // Like bind_group_layout::Builder, entries go one past the highest binding so far.
+    pub fn add_resource(&mut self, resource: wgpu::BindingResource<'a>) ->&mut Self {
+        let binding = self.entries.iter().map(|entry| entry.binding + 1).max().unwrap_or(0);
         self.entries.push(wgpu::BindGroupEntry {
-            binding: self.entries.len() as u32,
-            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                buffer: buffer,
-                offset: offset,
-                size: None,
-            }),
+            binding,
+            resource,
         });
+        self.views.push(Vec::new());
 
         self
     }
 
-    pub fn build(&mut self, label: &str) -> wgpu::BindGroup {
-        let bind_group = self.device.create_bind_group(
+    // Moves the last entry to @binding(index), the next one follows it.
+    #[allow(dead_code)]
+    pub fn set_binding(&mut self, index: u32) ->&mut Self {
+        self.entries.last_mut().expect("set_binding needs an entry to change, add one first").binding = index;
+
+        self
+    }
+
+    // Errors leave the builder reset just like a successful build.
+    pub fn build(&mut self, label: &str) -> Result<wgpu::BindGroup, BindGroupError> {
+        let result = self.create(label);
+
+        self.reset();
+
+        result
+    }
+
+    fn create(&self, label: &str) -> Result<wgpu::BindGroup, BindGroupError> {
+        let layout = self.layout.ok_or_else(|| BindGroupError::MissingLayout { label: label.to_string() })?;
+        self.check(layout).map_err(|(binding, message)| BindGroupError::Binding { label: label.to_string(), binding, message })?;
+
+        Ok(self.device.create_bind_group(
             &wgpu::BindGroupDescriptor {
-                layout: self.layout.unwrap(),
+                layout,
                 entries: &self.entries,
                 label: Some(label),
             }
-        );
-        
-        self.reset();
-        
-        bind_group
+        ))
     }
-}
\ No newline at end of file
+
+    fn check(&self, layout: &Layout) -> Result<(), (u32, String)> {
+        for (i, entry) in self.entries.iter().enumerate() {
+            if self.entries[..i].iter().any(|other| other.binding == entry.binding) {
+                return Err((entry.binding, "more than one resource is bound to it".to_string()));
+            }
+        }
+
+        for layout_entry in layout.entries() {
+            let i = self.entries.iter()
+                .position(|entry| entry.binding == layout_entry.binding)
+                .ok_or((layout_entry.binding, format!("the layout expects {:?} but nothing was added for it", layout_entry.ty)))?;
+            self.check_entry(layout_entry, &self.entries[i].resource, &self.views[i])
+                .map_err(|message| (layout_entry.binding, message))?;
+        }
+
+        match self.entries.iter().find(|entry| layout.entry(entry.binding).is_none()) {
+            Some(entry) => Err((entry.binding, "the layout has no entry for it".to_string())),
+            None => Ok(()),
+        }
+    }
+
+    fn check_entry(&self, layout_entry: &wgpu::BindGroupLayoutEntry, resource: &wgpu::BindingResource, views: &[View]) -> Result<(), String> {
+        use wgpu::BindingResource as R;
+
+        let Some(count) = layout_entry.count else {
+            return match resource {
+                R::Buffer(buffer) => self.check_buffer(&layout_entry.ty, buffer),
+                R::TextureView(_) => match views.first() {
+                    Some(view) => self.check_texture_view(&layout_entry.ty, view),
+                    None => Err("texture views need add_texture_view, add_resource doesn't know how they were created".to_string()),
+                },
+                R::Sampler(_) if matches!(layout_entry.ty, wgpu::BindingType::Sampler(_)) => Ok(()),
+                R::BufferArray(_) | R::TextureViewArray(_) | R::SamplerArray(_) => {
+                    Err("got an array but the layout entry isn't a binding array".to_string())
+                }
+                _ => Err(format!("the layout expects {:?}", layout_entry.ty)),
+            };
+        };
+
+        let length = match resource {
+            R::BufferArray(buffers) => buffers.len(),
+            R::TextureViewArray(views) => views.len(),
+            R::SamplerArray(samplers) => samplers.len(),
+            _ => return Err(format!("the layout expects a binding array of {} but got a single resource", count)),
+        };
+        let partially_bound = self.device.features().contains(wgpu::Features::PARTIALLY_BOUND_BINDING_ARRAY);
+        if length > count.get() as usize || (length < count.get() as usize && !partially_bound) {
+            return Err(format!("got {} resources for a binding array of {}", length, count));
+        }
+
+        match resource {
+            R::BufferArray(buffers) => buffers.iter().try_for_each(|buffer| self.check_buffer(&layout_entry.ty, buffer)),
+            R::TextureViewArray(_) if views.is_empty() => {
+                Err("texture view arrays need add_texture_view_array, add_resource doesn't know how they were created".to_string())
+            }
+            R::TextureViewArray(_) => views.iter().try_for_each(|view| self.check_texture_view(&layout_entry.ty, view)),
+            _ if matches!(layout_entry.ty, wgpu::BindingType::Sampler(_)) => Ok(()),
+            _ => Err(format!("the layout expects an array of {:?}", layout_entry.ty)),
+        }
+    }
+
+    fn check_buffer(&self, ty: &wgpu::BindingType, binding: &wgpu::BufferBinding) -> Result<(), String> {
+        let wgpu::BindingType::Buffer { ty, min_binding_size, .. } = ty else {
+            return Err(format!("got a buffer but the layout expects {:?}", ty));
+        };
+        let limits = self.device.limits();
+        let (usage, alignment) = match ty {
+            wgpu::BufferBindingType::Uniform => (wgpu::BufferUsages::UNIFORM, limits.min_uniform_buffer_offset_alignment),
+            wgpu::BufferBindingType::Storage { .. } => (wgpu::BufferUsages::STORAGE, limits.min_storage_buffer_offset_alignment),
+        };
+
+        let buffer = binding.buffer;
+        if !buffer.usage().contains(usage) {
+            return Err(format!("the layout expects a {:?} buffer but its usage is {:?}", ty, buffer.usage()));
+        }
+        if !binding.offset.is_multiple_of(alignment as u64) {
+            return Err(format!("offset {} isn't a multiple of {}", binding.offset, alignment));
+        }
+        let size = match binding.size {
+            Some(size) => size.get(),
+            None => buffer.size().saturating_sub(binding.offset),
+        };
+        if binding.offset + size > buffer.size() {
+            return Err(format!("{} bytes from offset {} run past the end of the {} byte buffer", size, binding.offset, buffer.size()));
+        }
+        if let Some(min_binding_size) = min_binding_size
+            && size < min_binding_size.get() {
+            return Err(format!("binds {} bytes but the layout needs at least {}", size, min_binding_size));
+        }
+
+        Ok(())
+    }
+
+    fn check_texture_view(&self, ty: &wgpu::BindingType, view: &View) -> Result<(), String> {
+        let texture = &view.texture;
+        let (view_dimension, usage) = match ty {
+            wgpu::BindingType::Texture { sample_type, view_dimension, multisampled } => {
+                // Combined depth-stencil formats are sampled through the aspect the view picked
+                let aspect = (view.aspect != wgpu::TextureAspect::All).then_some(view.aspect);
+                let format_sample_type = view.format.sample_type(aspect, Some(self.device.features()))
+                    .ok_or_else(|| format!("{:?} can't be sampled through {:?}", view.format, view.aspect))?;
+                if !sample_type_compatible(*sample_type, format_sample_type) {
+                    return Err(format!("the layout expects {:?} samples but {:?} gives {:?}", sample_type, view.format, format_sample_type));
+                }
+                if *multisampled != (texture.sample_count() > 1) {
+                    return Err(format!("the layout expects multisampled: {} but the texture has {} samples", multisampled, texture.sample_count()));
+                }
+                (view_dimension, wgpu::TextureUsages::TEXTURE_BINDING)
+            }
+            wgpu::BindingType::StorageTexture { format, view_dimension, .. } => {
+                if *format != view.format {
+                    return Err(format!("the layout expects {:?} but the view is {:?}", format, view.format));
+                }
+                (view_dimension, wgpu::TextureUsages::STORAGE_BINDING)
+            }
+            ty => return Err(format!("got a texture view but the layout expects {:?}", ty)),
+        };
+
+        if !texture.usage().contains(usage) {
+            return Err(format!("the texture's usage {:?} lacks {:?}", texture.usage(), usage));
+        }
+        if *view_dimension != view.dimension {
+            return Err(format!("the layout expects a {:?} view but got a {:?} one", view_dimension, view.dimension));
+        }
+
+        Ok(())
+    }
+}
+
+// A texture view as its descriptor resolved it.
+struct View {
+    texture: wgpu::Texture,
+    format: wgpu::TextureFormat,
+    dimension: wgpu::TextureViewDimension,
+    aspect: wgpu::TextureAspect,
+}
+
+impl View {
+
+    // Fills in what the descriptor leaves to the texture the way wgpu does.
+    fn new(view: &wgpu::TextureView, descriptor: &wgpu::TextureViewDescriptor) -> Self {
+        let texture = view.texture().clone();
+        let dimension = descriptor.dimension.unwrap_or(match texture.dimension() {
+            wgpu::TextureDimension::D1 => wgpu::TextureViewDimension::D1,
+            wgpu::TextureDimension::D2 if texture.depth_or_array_layers() == 1 => wgpu::TextureViewDimension::D2,
+            wgpu::TextureDimension::D2 => wgpu::TextureViewDimension::D2Array,
+            wgpu::TextureDimension::D3 => wgpu::TextureViewDimension::D3,
+        });
+
+        Self {
+            format: descriptor.format.unwrap_or(texture.format()),
+            dimension,
+            aspect: descriptor.aspect,
+            texture,
+        }
+    }
+}
+
+// Depth textures can be read as unfilterable floats, filterable floats work where unfilterable ones do.
+fn sample_type_compatible(layout: wgpu::TextureSampleType, format: wgpu::TextureSampleType) -> bool {
+    use wgpu::TextureSampleType::{Depth, Float};
+
+    match (layout, format) {
+        (Float { filterable: true }, Float { filterable }) => filterable,
+        (Float { filterable: false }, Float { .. } | Depth) => true,
+        (layout, format) => layout == format,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Builder, sample_type_compatible};
+    use crate::renderer_backend::{bind_group_layout, error::BindGroupError, testing};
+
+    #[test]
+    fn depth_textures_read_as_unfilterable_floats() {
+        use wgpu::TextureSampleType::{Depth, Float, Uint};
+
+        assert!(sample_type_compatible(Float { filterable: false }, Depth));
+        assert!(sample_type_compatible(Float { filterable: false }, Float { filterable: true }));
+        assert!(!sample_type_compatible(Float { filterable: true }, Float { filterable: false }));
+        assert!(!sample_type_compatible(Float { filterable: true }, Depth));
+        assert!(!sample_type_compatible(Uint, Float { filterable: true }));
+    }
+
+    #[test]
+    fn builder_reports_mismatches() {
+        let (device, _queue) = testing::device();
+
+        let texture = |format, usage| testing::texture(&device, format, usage)
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let color = texture(wgpu::TextureFormat::Rgba8Unorm, wgpu::TextureUsages::TEXTURE_BINDING);
+        let ids = texture(wgpu::TextureFormat::R32Uint, wgpu::TextureUsages::TEXTURE_BINDING);
+        let not_bindable = texture(wgpu::TextureFormat::Rgba8Unorm, wgpu::TextureUsages::COPY_DST);
+        let buffer = |usage| device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Test Buffer"),
+            size: 256,
+            usage,
+            mapped_at_creation: false,
+        });
+        let uniforms = buffer(wgpu::BufferUsages::UNIFORM);
+        let storage = buffer(wgpu::BufferUsages::STORAGE);
+
+        let mut builder = bind_group_layout::Builder::new(&device);
+        builder.add_uniform_buffer(wgpu::ShaderStages::FRAGMENT).set_min_binding_size(64)
+            .add_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureSampleType::Float { filterable: true },
+                wgpu::TextureViewDimension::D2).set_binding(3);
+        let layout = builder.build("Checked Layout");
+
+        let mut builder = Builder::new(&device);
+        let error = builder.add_buffer(&uniforms, 0).build("No Layout").unwrap_err();
+        assert!(matches!(error, BindGroupError::MissingLayout { .. }), "{}", error);
+
+        let mut check = |uniforms, view, binding| {
+            builder.set_layout(&layout).add_resource(uniforms)
+                .add_texture_view(view, &wgpu::TextureViewDescriptor::default()).set_binding(binding);
+            builder.build("Checked Bind Group")
+        };
+        let buffer = |buffer, offset| wgpu::BindingResource::Buffer(wgpu::BufferBinding { buffer, offset, size: None });
+
+        assert!(check(buffer(&uniforms, 0), &color, 3).is_ok());
+        let expect_error = |result: Result<wgpu::BindGroup, BindGroupError>, binding| match result {
+            Err(BindGroupError::Binding { binding: actual, .. }) if actual == binding => {}
+            Err(e) => panic!("expected an error at @binding({}), got {}", binding, e),
+            Ok(_) => panic!("expected an error at @binding({})", binding),
+        };
+        expect_error(check(buffer(&storage, 0), &color, 3), 0);
+        expect_error(check(buffer(&uniforms, 224), &color, 3), 0);
+        expect_error(check(wgpu::BindingResource::TextureView(&color), &color, 3), 0);
+        expect_error(check(buffer(&uniforms, 0), &ids, 3), 3);
+        expect_error(check(buffer(&uniforms, 0), &not_bindable, 3), 3);
+        expect_error(check(buffer(&uniforms, 0), &color, 2), 3);
+    }
+
+    #[test]
+    fn depth_stencil_textures_bind_through_their_aspect() {
+        let (device, _queue) = testing::device();
+        let texture = testing::texture(&device, wgpu::TextureFormat::Depth24PlusStencil8, wgpu::TextureUsages::TEXTURE_BINDING);
+        let depth = wgpu::TextureViewDescriptor { aspect: wgpu::TextureAspect::DepthOnly, ..Default::default() };
+        let stencil = wgpu::TextureViewDescriptor { aspect: wgpu::TextureAspect::StencilOnly, ..Default::default() };
+
+        let check = |sample_type, descriptor: &wgpu::TextureViewDescriptor| {
+            let layout = bind_group_layout::Builder::new(&device)
+                .add_texture(wgpu::ShaderStages::FRAGMENT, sample_type, wgpu::TextureViewDimension::D2)
+                .build("Depth Stencil Layout");
+            let view = texture.create_view(descriptor);
+            let mut builder = Builder::new(&device);
+            builder.set_layout(&layout).add_texture_view(&view, descriptor);
+            builder.build("Depth Stencil Bind Group")
+        };
+
+        assert!(check(wgpu::TextureSampleType::Depth, &depth).is_ok());
+        assert!(check(wgpu::TextureSampleType::Uint, &stencil).is_ok());
+        assert!(check(wgpu::TextureSampleType::Uint, &depth).is_err());
+        assert!(check(wgpu::TextureSampleType::Depth, &stencil).is_err());
+        assert!(check(wgpu::TextureSampleType::Float { filterable: true }, &depth).is_err());
+    }
+
+    #[test]
+    fn views_match_the_layout_dimension() {
+        let (device, _queue) = testing::device();
+        let texture = testing::texture(&device, wgpu::TextureFormat::Rgba8Unorm, wgpu::TextureUsages::TEXTURE_BINDING);
+        let d2 = wgpu::TextureViewDescriptor::default();
+        let d2_array = wgpu::TextureViewDescriptor { dimension: Some(wgpu::TextureViewDimension::D2Array), ..Default::default() };
+
+        let check = |view_dimension, descriptor: &wgpu::TextureViewDescriptor| {
+            let layout = bind_group_layout::Builder::new(&device)
+                .add_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureSampleType::Float { filterable: true }, view_dimension)
+                .build("Dimension Layout");
+            let view = texture.create_view(descriptor);
+            let mut builder = Builder::new(&device);
+            builder.set_layout(&layout).add_texture_view(&view, descriptor);
+            builder.build("Dimension Bind Group")
+        };
+
+        // The texture allows both, the view picks one
+        assert!(check(wgpu::TextureViewDimension::D2, &d2).is_ok());
+        assert!(check(wgpu::TextureViewDimension::D2Array, &d2_array).is_ok());
+        assert!(check(wgpu::TextureViewDimension::D2, &d2_array).is_err());
+        assert!(check(wgpu::TextureViewDimension::D2Array, &d2).is_err());
+    }
+}
@@ -1,10 +1,21 @@
+use std::num::{NonZeroU32, NonZeroU64};
+use std::ops::Deref;
 
+use super::resource_cache::ResourceCache;
 
+// Every add_* appends an entry at one past the highest binding so far, the set_* methods
+// below adjust the entry added last:
+//
+//     builder.add_uniform_buffer(wgpu::ShaderStages::VERTEX).set_binding(2).set_dynamic_offset(true)
+//         .add_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureSampleType::Depth, wgpu::TextureViewDimension::Cube)
+//         .add_sampler(wgpu::ShaderStages::FRAGMENT, wgpu::SamplerBindingType::Comparison);
+#[allow(dead_code)]
 pub struct Builder<'a> {
     entries: Vec<wgpu::BindGroupLayoutEntry>,
     device: &'a wgpu::Device,
 }
 
+#[allow(dead_code)]
 impl<'a> Builder<'a> {
 
     pub fn new(device: &'a wgpu::Device) -> Self {
@@ -20,53 +31,257 @@ impl<'a> Builder<'a> {
     }
 
     pub fn add_material(&mut self) ->&mut Self {
+        self.add_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureSampleType::Float { filterable: true },
+            wgpu::TextureViewDimension::D2);
+        self.add_sampler(wgpu::ShaderStages::FRAGMENT, wgpu::SamplerBindingType::Filtering)
+    }
 
-        self.entries.push(wgpu::BindGroupLayoutEntry {
-            binding: self.entries.len() as u32,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Texture {
-                multisampled: false,
-                view_dimension: wgpu::TextureViewDimension::D2,
-                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-            },
-            count: None,
-        });
+    pub fn add_ubo(&mut self) ->&mut Self {
+        self.add_uniform_buffer(wgpu::ShaderStages::VERTEX_FRAGMENT)
+    }
+
+    pub fn add_uniform_buffer(&mut self, visibility: wgpu::ShaderStages) -> &mut Self {
+        self.add_entry(visibility, wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        })
+    }
+
+    pub fn add_storage_buffer(&mut self, visibility: wgpu::ShaderStages, read_only: bool) -> &mut Self {
+        self.add_entry(visibility, wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        })
+    }
+
+    // Depth textures use TextureSampleType::Depth, cube maps, arrays and 3D textures the matching dimension.
+    pub fn add_texture(&mut self, visibility: wgpu::ShaderStages, sample_type: wgpu::TextureSampleType,
+        view_dimension: wgpu::TextureViewDimension) -> &mut Self {
+
+        self.add_entry(visibility, wgpu::BindingType::Texture {
+            sample_type,
+            view_dimension,
+            multisampled: false,
+        })
+    }
+
+    // For texture_multisampled_2d, these can't be filtered.
+    pub fn add_multisampled_texture(&mut self, visibility: wgpu::ShaderStages, sample_type: wgpu::TextureSampleType) -> &mut Self {
+        self.add_entry(visibility, wgpu::BindingType::Texture {
+            sample_type,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: true,
+        })
+    }
+
+    // ReadWrite and ReadOnly access only work for some formats, see TextureFormatFeatureFlags::STORAGE_READ_WRITE.
+    // 2D unless set_view_dimension says otherwise.
+    pub fn add_storage_texture(&mut self, visibility: wgpu::ShaderStages, format: wgpu::TextureFormat,
+        access: wgpu::StorageTextureAccess) -> &mut Self {
+
+        self.add_entry(visibility, wgpu::BindingType::StorageTexture {
+            access,
+            format,
+            view_dimension: wgpu::TextureViewDimension::D2,
+        })
+    }
 
+    // Comparison samplers go with depth textures for shadow lookups.
+    pub fn add_sampler(&mut self, visibility: wgpu::ShaderStages, sampler_type: wgpu::SamplerBindingType) -> &mut Self {
+        self.add_entry(visibility, wgpu::BindingType::Sampler(sampler_type))
+    }
+
+    // Anything the helpers above don't cover.
+    pub fn add_entry(&mut self, visibility: wgpu::ShaderStages, ty: wgpu::BindingType) -> &mut Self {
+        let binding = self.entries.iter().map(|entry| entry.binding + 1).max().unwrap_or(0);
         self.entries.push(wgpu::BindGroupLayoutEntry {
-            binding: self.entries.len() as u32,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            binding,
+            visibility,
+            ty,
             count: None,
         });
-        
+
         self
     }
-    
-    pub fn add_ubo(&mut self) ->&mut Self {
-        self.entries.push(wgpu::BindGroupLayoutEntry {
-            binding: self.entries.len() as u32,
-            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: None,
-            },
-            count: None,
-        });
-        
+
+    // Moves the last entry to @binding(index), the next one follows it.
+    pub fn set_binding(&mut self, index: u32) -> &mut Self {
+        self.last_entry("set_binding").binding = index;
+
+        self
+    }
+
+    pub fn set_visibility(&mut self, visibility: wgpu::ShaderStages) -> &mut Self {
+        self.last_entry("set_visibility").visibility = visibility;
+
+        self
+    }
+
+    // Makes the last entry a binding array of `count` elements, e.g. binding_array<texture_2d<f32>, 16>.
+    // Needs Features::TEXTURE_BINDING_ARRAY or its buffer and storage counterparts.
+    pub fn set_count(&mut self, count: u32) -> &mut Self {
+        self.last_entry("set_count").count = NonZeroU32::new(count);
+
+        self
+    }
+
+    // Only for buffers, the offset is then passed to set_bind_group.
+    pub fn set_dynamic_offset(&mut self, dynamic: bool) -> &mut Self {
+        match &mut self.last_entry("set_dynamic_offset").ty {
+            wgpu::BindingType::Buffer { has_dynamic_offset, .. } => *has_dynamic_offset = dynamic,
+            ty => panic!("set_dynamic_offset needs a buffer entry, not {:?}", ty),
+        }
+
+        self
+    }
+
+    // Only for buffers, lets wgpu check buffer sizes when the bind group is created instead of at every draw.
+    pub fn set_min_binding_size(&mut self, size: u64) -> &mut Self {
+        match &mut self.last_entry("set_min_binding_size").ty {
+            wgpu::BindingType::Buffer { min_binding_size, .. } => *min_binding_size = NonZeroU64::new(size),
+            ty => panic!("set_min_binding_size needs a buffer entry, not {:?}", ty),
+        }
+
         self
     }
 
-    pub fn build(&mut self, label: &str) -> wgpu::BindGroupLayout {
+    // Only for textures and storage textures.
+    pub fn set_view_dimension(&mut self, dimension: wgpu::TextureViewDimension) -> &mut Self {
+        match &mut self.last_entry("set_view_dimension").ty {
+            wgpu::BindingType::Texture { view_dimension, .. } |
+            wgpu::BindingType::StorageTexture { view_dimension, .. } => *view_dimension = dimension,
+            ty => panic!("set_view_dimension needs a texture entry, not {:?}", ty),
+        }
 
-        let layout = self.device.create_bind_group_layout(
+        self
+    }
+
+    fn last_entry(&mut self, method: &str) -> &mut wgpu::BindGroupLayoutEntry {
+        self.entries.last_mut().unwrap_or_else(|| panic!("{} needs an entry to change, add one first", method))
+    }
+
+    // set_binding can move an entry onto one that's already there, wgpu would only report it later.
+    fn check_bindings(&self, label: &str) {
+        for (i, entry) in self.entries.iter().enumerate() {
+            if self.entries[..i].iter().any(|other| other.binding == entry.binding) {
+                panic!("{} has two entries for @binding({})", label, entry.binding);
+            }
+        }
+    }
+
+    // What has been added so far, e.g. for reflection::Reflection::check_bind_group_layout.
+    pub fn entries(&self) -> &[wgpu::BindGroupLayoutEntry] {
+        &self.entries
+    }
+
+    pub fn build(&mut self, label: &str) -> Layout {
+        self.check_bindings(label);
+        let layout = Layout::new(self.device, &self.entries, label);
+        self.reset();
+
+        layout
+    }
+
+    // Like build, but returns the cache's layout if an equal one was built before.
+    pub fn build_cached(&mut self, cache: &mut ResourceCache, label: &str) -> Layout {
+        self.check_bindings(label);
+        let layout = cache.bind_group_layout(self.device, &self.entries, label);
+        self.reset();
+
+        layout
+    }
+}
+
+// A bind group layout that keeps its entries, so bind_group::Builder can check resources
+// against them. Derefs to the wgpu layout for pipeline builders.
+#[derive(Clone, Debug)]
+pub struct Layout {
+    layout: wgpu::BindGroupLayout,
+    entries: Vec<wgpu::BindGroupLayoutEntry>,
+}
+
+impl Layout {
+
+    pub fn new(device: &wgpu::Device, entries: &[wgpu::BindGroupLayoutEntry], label: &str) -> Self {
+        let layout = device.create_bind_group_layout(
             &wgpu::BindGroupLayoutDescriptor {
-                entries: &self.entries,
+                entries,
                 label: Some(label),
             }
         );
-        self.reset();
 
-        layout
+        Self { layout, entries: entries.to_vec() }
+    }
+
+    pub fn entries(&self) -> &[wgpu::BindGroupLayoutEntry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, binding: u32) -> Option<&wgpu::BindGroupLayoutEntry> {
+        self.entries.iter().find(|entry| entry.binding == binding)
     }
-}
\ No newline at end of file
+}
+
+impl Deref for Layout {
+    type Target = wgpu::BindGroupLayout;
+
+    fn deref(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+}
+
+impl PartialEq for Layout {
+    fn eq(&self, other: &Self) -> bool {
+        self.layout == other.layout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Builder;
+    use crate::renderer_backend::{compute_pipeline, reflection::Reflection, shader, testing};
+
+    #[test]
+    fn builder_matches_every_binding_kind() {
+        let (device, _queue) = testing::device();
+        let compute = wgpu::ShaderStages::COMPUTE;
+        let float = wgpu::TextureSampleType::Float { filterable: true };
+
+        let mut builder = Builder::new(&device);
+        builder.add_uniform_buffer(compute).set_dynamic_offset(true).set_min_binding_size(16)
+            .add_storage_buffer(compute, true).set_binding(2)
+            .add_storage_buffer(compute, false)
+            .add_texture(compute, wgpu::TextureSampleType::Depth, wgpu::TextureViewDimension::D2).set_binding(5)
+            .add_sampler(compute, wgpu::SamplerBindingType::Comparison)
+            .add_texture(compute, float, wgpu::TextureViewDimension::Cube)
+            .add_texture(compute, float, wgpu::TextureViewDimension::D2Array)
+            .add_texture(compute, float, wgpu::TextureViewDimension::D3)
+            .add_sampler(compute, wgpu::SamplerBindingType::Filtering)
+            .add_storage_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureFormat::Rgba8Unorm, wgpu::StorageTextureAccess::WriteOnly)
+            .set_binding(12).set_visibility(compute);
+
+        let bindings: Vec<u32> = builder.entries().iter().map(|entry| entry.binding).collect();
+        assert_eq!(bindings, [0, 2, 3, 5, 6, 7, 8, 9, 10, 12]);
+        let reflection = Reflection::new(&shader::parse("shaders/layout_test.wgsl", &[]).unwrap()).unwrap();
+        reflection.check_bind_group_layout(0, builder.entries()).unwrap();
+
+        let layout = builder.build("Every Binding Kind Layout");
+        let mut builder = compute_pipeline::Builder::new(&device);
+        builder.set_shader_module("shaders/layout_test.wgsl", "cs_main")
+            .add_bind_group_layout(&layout);
+        builder.build("Every Binding Kind Pipeline").unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Duplicate Layout has two entries for @binding(1)")]
+    fn build_rejects_duplicate_bindings() {
+        let (device, _queue) = testing::device();
+        let mut builder = Builder::new(&device);
+        builder.add_uniform_buffer(wgpu::ShaderStages::VERTEX)
+            .add_uniform_buffer(wgpu::ShaderStages::VERTEX)
+            .add_uniform_buffer(wgpu::ShaderStages::VERTEX).set_binding(1);
+        builder.build("Duplicate Layout");
+    }
+}
@@ -0,0 +1,75 @@
+use super::bind_group;
+use super::bind_group_layout::Layout;
+use super::error::BindGroupError;
+use super::material::Material;
+
+// Size of the binding_array in shaders/common.wgsl.
+pub const MAX_MATERIALS: usize = 8;
+// mySampler in shaders/common.wgsl, right after the texture array.
+const SAMPLER_BINDING: u32 = 1;
+
+// Whether to request Features::TEXTURE_BINDING_ARRAY and the binding array limit with it.
+pub fn available(adapter: &wgpu::Adapter) -> bool {
+    adapter.features().contains(wgpu::Features::TEXTURE_BINDING_ARRAY)
+        && adapter.limits().max_binding_array_elements_per_shader_stage >= MAX_MATERIALS as u32
+}
+
+pub fn supported(device: &wgpu::Device) -> bool {
+    device.features().contains(wgpu::Features::TEXTURE_BINDING_ARRAY)
+}
+
+// One bind group with every material's texture, a material's index in `materials` is what
+// the shader gets as ObjectData.material. Unused slots repeat the first texture unless the
+// device allows partially bound arrays. The shader has one sampler, so every material has to
+// use the same one, e.g. from a ResourceCache.
+pub fn build_bind_group(device: &wgpu::Device, layout: &Layout, materials: &[&Material], label: &str)
+    -> Result<wgpu::BindGroup, BindGroupError> {
+
+    if let Some((i, _)) = materials.iter().enumerate().find(|(_, material)| material.sampler != materials[0].sampler) {
+        return Err(BindGroupError::Binding {
+            label: label.to_string(),
+            binding: SAMPLER_BINDING,
+            message: format!("material {} has a different sampler than material 0, bindless materials share one", i),
+        });
+    }
+
+    let mut views: Vec<&wgpu::TextureView> = materials.iter().map(|material| &material.view).collect();
+    if !device.features().contains(wgpu::Features::PARTIALLY_BOUND_BINDING_ARRAY)
+        && let Some(first) = views.first().copied() {
+        views.resize(views.len().max(MAX_MATERIALS), first);
+    }
+
+    let mut builder = bind_group::Builder::new(device);
+    // Material::new creates its views with the default descriptor
+    builder.set_layout(layout).add_texture_view_array(&views, &wgpu::TextureViewDescriptor::default());
+    if let Some(material) = materials.first() {
+        builder.add_sampler(&material.sampler);
+    }
+    builder.build(label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build_bind_group;
+    use crate::renderer_backend::{bind_group_layout::Layout, error::BindGroupError, material::Material, testing};
+
+    #[test]
+    fn materials_have_to_share_their_sampler() {
+        let (device, _queue) = testing::device();
+        let material = |filter| {
+            let texture = testing::texture(&device, wgpu::TextureFormat::Rgba8Unorm, wgpu::TextureUsages::TEXTURE_BINDING);
+            Material {
+                view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                sampler: device.create_sampler(&wgpu::SamplerDescriptor { mag_filter: filter, ..Default::default() }),
+                bind_group: None,
+            }
+        };
+        let nearest = material(wgpu::FilterMode::Nearest);
+        let linear = material(wgpu::FilterMode::Linear);
+
+        // Checked before the layout is looked at
+        let layout = Layout::new(&device, &[], "Empty Layout");
+        let error = build_bind_group(&device, &layout, &[&nearest, &linear], "Bindless Materials").unwrap_err();
+        assert!(matches!(error, BindGroupError::Binding { binding: 1, .. }), "{}", error);
+    }
+}
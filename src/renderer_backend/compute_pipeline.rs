@@ -0,0 +1,197 @@
+use super::bind_group_layout::Layout;
+use super::error::PipelineError;
+use super::pipeline::ShaderSetup;
+
+pub struct Builder<'a> {
+    shader: ShaderSetup,
+    entry: String,
+    bind_group_layouts: Vec<&'a Layout>,
+    cache: Option<&'a wgpu::PipelineCache>,
+    device: &'a wgpu::Device,
+}
+
+impl<'a> Builder<'a> {
+    #[allow(dead_code)]
+    pub fn new(device: &'a wgpu::Device) -> Self {
+        Self {
+            shader: ShaderSetup::new(),
+            entry: "dummy".to_string(),
+            bind_group_layouts: Vec::new(),
+            cache: None,
+            device
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.bind_group_layouts.clear();
+    }
+
+    #[allow(dead_code)]
+    pub fn add_bind_group_layout(&mut self, layout: &'a Layout) -> &mut Self {
+        self.bind_group_layouts.push(layout);
+
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn set_cache(&mut self, cache: Option<&'a wgpu::PipelineCache>) -> &mut Self {
+        self.cache = cache;
+
+        self
+    }
+
+    // Like pipeline::Builder, with a single compute entry point.
+    #[allow(dead_code)]
+    pub fn set_shader_module(&mut self, shader_filename: &str, entry: &str) -> &mut Self {
+        self.shader.set_filename(shader_filename);
+        self.entry = entry.to_string();
+
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn add_define(&mut self, name: &str) -> &mut Self {
+        self.shader.add_define(name);
+
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn set_constant(&mut self, name: &str, value: f64) -> &mut Self {
+        self.shader.set_constant(name, value);
+
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn build(&mut self, label: &str) -> Result<wgpu::ComputePipeline, PipelineError> {
+        let result = self.create(label);
+
+        self.reset();
+
+        result
+    }
+
+    fn create(&self, label: &str) -> Result<wgpu::ComputePipeline, PipelineError> {
+        self.shader.create_pipeline(self.device, label, &self.bind_group_layouts, 0, |_| Ok(()),
+            |shader_module, constants, pipeline_layout| {
+                let compute_pipeline_descriptor = wgpu::ComputePipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(pipeline_layout),
+                    module: shader_module,
+                    entry_point: Some(&self.entry),
+                    compilation_options: wgpu::PipelineCompilationOptions {
+                        constants,
+                        ..Default::default()
+                    },
+                    cache: self.cache,
+                };
+
+                self.device.create_compute_pipeline(&compute_pipeline_descriptor)
+            })
+    }
+}
+
+// Workgroups needed to cover `size` items when every workgroup handles `workgroup_size` of them,
+// the shader has to skip the invocations past the end.
+#[allow(dead_code)]
+pub fn workgroup_count(size: u32, workgroup_size: u32) -> u32 {
+    assert!(workgroup_size > 0, "workgroup_size must be at least 1, like @workgroup_size in the shader");
+    size.div_ceil(workgroup_size)
+}
+
+// Records a compute pass that binds `bind_groups` to groups 0, 1, ... and dispatches `workgroups`.
+#[allow(dead_code)]
+pub fn dispatch(command_encoder: &mut wgpu::CommandEncoder, pipeline: &wgpu::ComputePipeline,
+    bind_groups: &[&wgpu::BindGroup], workgroups: (u32, u32, u32), label: &str) {
+
+    let compute_pass_descriptor = wgpu::ComputePassDescriptor {
+        label: Some(label),
+        timestamp_writes: None,
+    };
+    let mut compute_pass = command_encoder.begin_compute_pass(&compute_pass_descriptor);
+    compute_pass.set_pipeline(pipeline);
+    for (i, bind_group) in bind_groups.iter().enumerate() {
+        compute_pass.set_bind_group(i as u32, *bind_group, &[]);
+    }
+    compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, workgroups.2);
+}
+
+// Covers a width x height image with 2D workgroups, e.g. for image processing.
+#[allow(dead_code)]
+pub fn dispatch_2d(command_encoder: &mut wgpu::CommandEncoder, pipeline: &wgpu::ComputePipeline,
+    bind_groups: &[&wgpu::BindGroup], size: (u32, u32), workgroup_size: (u32, u32), label: &str) {
+
+    let workgroups = (workgroup_count(size.0, workgroup_size.0), workgroup_count(size.1, workgroup_size.1), 1);
+    dispatch(command_encoder, pipeline, bind_groups, workgroups, label);
+}
+
+#[cfg(test)]
+mod tests {
+    use wgpu::util::DeviceExt;
+
+    use super::{Builder, dispatch, workgroup_count};
+    use crate::renderer_backend::{bind_group, bind_group_layout, testing};
+
+    #[test]
+    fn workgroup_count_rounds_up() {
+        assert_eq!(workgroup_count(64, 64), 1);
+        assert_eq!(workgroup_count(65, 64), 2);
+        assert_eq!(workgroup_count(0, 64), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "workgroup_size must be at least 1")]
+    fn workgroup_count_rejects_empty_workgroups() {
+        workgroup_count(64, 0);
+    }
+
+    #[test]
+    fn compute_pipeline_doubles_storage_buffer() {
+        let (device, queue) = testing::device();
+
+        let values: Vec<u32> = (0..100).collect();
+        let size = (values.len() * std::mem::size_of::<u32>()) as u64;
+        let storage = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Storage Buffer"),
+            contents: bytemuck::cast_slice(&values),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let layout = {
+            let mut builder = bind_group_layout::Builder::new(&device);
+            builder.add_storage_buffer(wgpu::ShaderStages::COMPUTE, false);
+            builder.build("Storage Bind Group Layout")
+        };
+        let bind_group = {
+            let mut builder = bind_group::Builder::new(&device);
+            builder.set_layout(&layout)
+                .add_buffer(&storage, 0);
+            builder.build("Storage Bind Group").unwrap()
+        };
+        let pipeline = {
+            let mut builder = Builder::new(&device);
+            builder.set_shader_module("shaders/compute_test.wgsl", "cs_main")
+                .add_bind_group_layout(&layout);
+            builder.build("Compute Pipeline").unwrap()
+        };
+
+        let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        let workgroups = workgroup_count(values.len() as u32, 64);
+        dispatch(&mut command_encoder, &pipeline, &[&bind_group], (workgroups, 1, 1), "Double Pass");
+        command_encoder.copy_buffer_to_buffer(&storage, 0, &staging, 0, size);
+        queue.submit(std::iter::once(command_encoder.finish()));
+
+        staging.slice(..).map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::PollType::wait_indefinitely()).unwrap();
+        let doubled: Vec<u32> = bytemuck::cast_slice(&staging.slice(..).get_mapped_range()).to_vec();
+
+        assert_eq!(doubled, values.iter().map(|value| value * 2).collect::<Vec<u32>>());
+    }
+}
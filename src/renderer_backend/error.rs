@@ -0,0 +1,228 @@
+use std::fmt;
+use std::path::PathBuf;
+
+// 1-based line and column, the column counts bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SourceLocation {
+    pub line: u32,
+    pub column: u32,
+}
+
+impl From<naga::SourceLocation> for SourceLocation {
+    fn from(location: naga::SourceLocation) -> Self {
+        Self { line: location.line_number, column: location.line_position }
+    }
+}
+
+#[derive(Debug)]
+pub enum PipelineError {
+    // An #include or the shader itself couldn't be loaded
+    MissingFile { path: PathBuf, source: AssetError },
+    // A broken #include, #define or #ifdef
+    Preprocess { path: PathBuf, line: u32, message: String },
+    // The WGSL doesn't parse
+    Parse { path: PathBuf, location: Option<SourceLocation>, message: String },
+    // The WGSL parses but naga or wgpu reject the module
+    Validation { path: PathBuf, location: Option<SourceLocation>, message: String },
+    // A binding the shader declares doesn't match its layout entry, or can't be reflected
+    Binding { group: u32, binding: u32, message: String },
+    // A vertex shader input doesn't match the vertex buffer layouts
+    VertexInput { location: u32, message: String },
+    // wgpu rejected the pipeline itself, usually because the bind group or vertex
+    // buffer layouts don't match what the shader declares
+    Pipeline { label: String, message: String },
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::MissingFile { path, source } => write!(f, "Can't load {}: {}", path.display(), source),
+            PipelineError::Preprocess { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+            PipelineError::Parse { path, location, message } => {
+                write!(f, "{}{}: parse error: {}", path.display(), Position(location), message)
+            }
+            PipelineError::Validation { path, location, message } => {
+                write!(f, "{}{}: validation error: {}", path.display(), Position(location), message)
+            }
+            PipelineError::Binding { group, binding, message } => {
+                write!(f, "@group({}) @binding({}): {}", group, binding, message)
+            }
+            PipelineError::VertexInput { location, message } => write!(f, "Vertex input @location({}): {}", location, message),
+            PipelineError::Pipeline { label, message } => write!(f, "Can't build {}: {}", label, message),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PipelineError::MissingFile { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AssetError {
+    // Not on disk in any search directory and not embedded either
+    NotFound { name: PathBuf, searched: Vec<PathBuf> },
+    Read { path: PathBuf, source: std::io::Error },
+    // The file was read but isn't valid UTF-8 text or a readable image
+    Decode { path: PathBuf, message: String },
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::NotFound { name, searched } => {
+                write!(f, "{} isn't embedded and wasn't found at", name.display())?;
+                for (i, path) in searched.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { "" } else { "," }, path.display())?;
+                }
+                Ok(())
+            }
+            AssetError::Read { path, source } => write!(f, "Can't read {}: {}", path.display(), source),
+            AssetError::Decode { path, message } => write!(f, "Can't decode {}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AssetError::Read { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BindGroupError {
+    // build was called before set_layout
+    MissingLayout { label: String },
+    // A resource doesn't fit the layout entry at its binding, or a layout entry has no resource
+    Binding { label: String, binding: u32, message: String },
+}
+
+impl fmt::Display for BindGroupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindGroupError::MissingLayout { label } => write!(f, "Can't build {}: no layout set", label),
+            BindGroupError::Binding { label, binding, message } => {
+                write!(f, "Can't build {}: @binding({}): {}", label, binding, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BindGroupError {}
+
+// Anything that keeps the renderer from starting, main prints it instead of panicking.
+#[derive(Debug)]
+pub enum Error {
+    // No adapter matches --adapter, or none is available at all
+    Adapter(String),
+    Asset(AssetError),
+    Pipeline(PipelineError),
+    BindGroup(BindGroupError),
+    // The rendered frame couldn't be copied back from the GPU
+    Readback(wgpu::BufferAsyncError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Adapter(message) => write!(f, "Can't get an adapter: {}", message),
+            Error::Asset(error) => error.fmt(f),
+            Error::Pipeline(error) => error.fmt(f),
+            Error::BindGroup(error) => error.fmt(f),
+            Error::Readback(error) => write!(f, "Can't read the frame back: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Adapter(_) => None,
+            Error::Asset(error) => error.source(),
+            Error::Pipeline(error) => error.source(),
+            Error::BindGroup(error) => error.source(),
+            Error::Readback(_) => None,
+        }
+    }
+}
+
+impl From<AssetError> for Error {
+    fn from(error: AssetError) -> Self {
+        Error::Asset(error)
+    }
+}
+
+impl From<PipelineError> for Error {
+    fn from(error: PipelineError) -> Self {
+        Error::Pipeline(error)
+    }
+}
+
+impl From<BindGroupError> for Error {
+    fn from(error: BindGroupError) -> Self {
+        Error::BindGroup(error)
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for Error {
+    fn from(error: wgpu::BufferAsyncError) -> Self {
+        Error::Readback(error)
+    }
+}
+
+struct Position<'a>(&'a Option<SourceLocation>);
+
+impl fmt::Display for Position<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(location) => write!(f, ":{}:{}", location.line, location.column),
+            None => Ok(()),
+        }
+    }
+}
+
+// wgpu and naga errors keep the interesting part in their sources.
+pub fn describe(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(": ");
+        message.push_str(&error.to_string());
+        source = error.source();
+    }
+
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{AssetError, PipelineError, SourceLocation};
+
+    #[test]
+    fn display_includes_line_and_column() {
+        let error = PipelineError::Parse {
+            path: PathBuf::from("shaders/shader.wgsl"),
+            location: Some(SourceLocation { line: 3, column: 14 }),
+            message: "expected ';'".to_string(),
+        };
+        assert_eq!(error.to_string(), "shaders/shader.wgsl:3:14: parse error: expected ';'");
+    }
+
+    #[test]
+    fn not_found_lists_the_searched_paths() {
+        let error = AssetError::NotFound {
+            name: PathBuf::from("img/a.jpg"),
+            searched: vec![PathBuf::from("/app/img/a.jpg"), PathBuf::from("/extra/img/a.jpg")],
+        };
+        assert_eq!(error.to_string(), "img/a.jpg isn't embedded and wasn't found at /app/img/a.jpg, /extra/img/a.jpg");
+    }
+}
@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::assets::{self, Locator};
+use super::preprocessor;
+
+// Notices edits to shader files and everything they #include by polling modification times.
+// Only files in the asset directory are watched, creating one that overrides an embedded
+// file counts as a change too.
+pub struct ShaderWatcher {
+    locator: Locator,
+    shaders: Vec<PathBuf>,
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ShaderWatcher {
+
+    pub fn new(locator: Locator) -> Self {
+        Self { locator, shaders: Vec::new(), files: Vec::new() }
+    }
+
+    pub fn watch(&mut self, shader_filename: &str) -> &mut Self {
+        let name = assets::normalize(shader_filename);
+        if !self.shaders.contains(&name) {
+            self.shaders.push(name);
+            self.update_files();
+        }
+
+        self
+    }
+
+    // True if any watched file changed since the last call.
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, last_modified) in self.files.iter_mut() {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+
+        // The edit may have added or removed an #include
+        if changed {
+            self.update_files();
+        }
+
+        changed
+    }
+
+    fn update_files(&mut self) {
+        let mut files: Vec<(PathBuf, Option<SystemTime>)> = Vec::new();
+        let paths = self.shaders.iter()
+            .flat_map(|shader| preprocessor::dependencies(&self.locator, shader))
+            .flat_map(|name| self.locator.candidates(name));
+        for path in paths {
+            if !files.iter().any(|(file, _)| *file == path) {
+                let modified = self.files.iter()
+                    .find(|(file, _)| *file == path)
+                    .map_or_else(|| modified(&path), |(_, modified)| *modified);
+                files.push((path, modified));
+            }
+        }
+        self.files = files;
+    }
+}
+
+// None while the file is missing, editors that save by replacing the file briefly get here.
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::ShaderWatcher;
+    use crate::renderer_backend::assets::Locator;
+
+    #[test]
+    fn notices_modified_includes() {
+        let directory = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("target/hot_reload");
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("watched.wgsl");
+        std::fs::write(&path, "#include \"included.wgsl\"\n").unwrap();
+        std::fs::write(directory.join("included.wgsl"), "").unwrap();
+
+        let mut watcher = ShaderWatcher::new(Locator::new(&directory));
+        watcher.watch("watched.wgsl");
+        assert!(!watcher.changed());
+
+        let file = std::fs::File::options().write(true).open(directory.join("included.wgsl")).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(1)).unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+    }
+}
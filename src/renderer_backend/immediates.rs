@@ -0,0 +1,49 @@
+use std::marker::PhantomData;
+
+// Whether to request Features::IMMEDIATES. The GL backend emulates them with uniforms and
+// panics when a program with immediates also samples a texture, so it's left out.
+pub fn available(adapter: &wgpu::Adapter) -> bool {
+    adapter.features().contains(wgpu::Features::IMMEDIATES) && adapter.get_info().backend != wgpu::Backend::Gl
+}
+
+pub fn supported(device: &wgpu::Device) -> bool {
+    device.features().contains(wgpu::Features::IMMEDIATES)
+}
+
+// A typed slot in a pipeline's immediate data, which is set per draw without a bind group.
+// Needs Features::IMMEDIATES, check `supported` and fall back to a uniform buffer without it.
+pub struct Immediate<T> {
+    offset: u32,
+    marker: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> Immediate<T> {
+
+    // Offsets have to be multiples of wgpu::IMMEDIATE_DATA_ALIGNMENT.
+    pub const fn new(offset: u32) -> Self {
+        assert!(offset.is_multiple_of(wgpu::IMMEDIATE_DATA_ALIGNMENT));
+        Self { offset, marker: PhantomData }
+    }
+
+    // Where the next slot can start, the last slot's end goes to pipeline::Builder::set_immediate_size.
+    pub const fn end(&self) -> u32 {
+        (self.offset + size_of::<T>() as u32).next_multiple_of(wgpu::IMMEDIATE_DATA_ALIGNMENT)
+    }
+
+    pub fn set(&self, renderpass: &mut wgpu::RenderPass, value: &T) {
+        renderpass.set_immediates(self.offset, bytemuck::bytes_of(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Immediate;
+
+    #[test]
+    fn slots_follow_each_other() {
+        const MODEL: Immediate<glam::Mat4> = Immediate::new(0);
+        const TINT: Immediate<[f32; 3]> = Immediate::new(MODEL.end());
+        assert_eq!(MODEL.end(), 64);
+        assert_eq!(TINT.end(), 76);
+    }
+}
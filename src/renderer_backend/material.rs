@@ -1,23 +1,21 @@
-use std::env::current_dir;
-
-use super::bind_group;
+use super::{assets, bind_group, error::{AssetError, Error}, bind_group_layout::Layout, resource_cache::ResourceCache};
 
 pub struct Material {
-    pub bind_group: wgpu::BindGroup,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    // None for bindless materials, see bindless::build_bind_group
+    pub bind_group: Option<wgpu::BindGroup>,
 }
 
 impl Material {
 
-    pub fn new(filename: &str, device: &wgpu::Device, queue: &wgpu::Queue, label: &str, layout: &wgpu::BindGroupLayout) -> Self {
-
-        // Get absolute filepath from relative one
-        let mut filepath = current_dir().unwrap();
-        filepath.push("src/");
-        filepath.push(filename);
-        let filepath = filepath.into_os_string().into_string().unwrap();
+    pub fn new(filename: &str, device: &wgpu::Device, queue: &wgpu::Queue, label: &str, layout: Option<&Layout>,
+        resources: &mut ResourceCache) -> Result<Self, Error> {
 
-        let bytes = std::fs::read(filepath).unwrap();
-        let loaded_image = image::load_from_memory(&bytes).unwrap();
+        // From the asset search paths or the copy embedded in the binary
+        let asset = assets::load(filename)?;
+        let loaded_image = image::load_from_memory(&asset.bytes)
+            .map_err(|e| AssetError::Decode { path: asset.path.clone(), message: e.to_string() })?;
         let converted = loaded_image.to_rgba8();
         use image::GenericImageView;
         let size = loaded_image.dimensions();
@@ -55,9 +53,10 @@ impl Material {
             texture_size);
 
         // Get a view of the texture
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let view_descriptor = wgpu::TextureViewDescriptor::default();
+        let view = texture.create_view(&view_descriptor);
 
-        // Make a sampler
+        // Make a sampler, materials share it
         let sampler_descriptor = wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::Repeat,
             address_mode_v: wgpu::AddressMode::Repeat,
@@ -67,17 +66,21 @@ impl Material {
             mipmap_filter: wgpu::MipmapFilterMode::Nearest,
             ..Default::default()
         };
-        let sampler = device.create_sampler(&sampler_descriptor);
+        let sampler = resources.sampler(device, &sampler_descriptor);
 
         // Make a bind group for everything
-        let mut builder = bind_group::Builder::new(device);
-        builder.set_layout(layout);
-        builder.add_material(&view, &sampler);
-        let bind_group = builder.build(label);
+        let bind_group = layout.map(|layout| {
+            let mut builder = bind_group::Builder::new(device);
+            builder.set_layout(layout);
+            builder.add_material(&view, &view_descriptor, &sampler);
+            builder.build(label)
+        }).transpose()?;
 
-        Material {
+        Ok(Material {
+            view,
+            sampler,
             bind_group,
-        }
+        })
 
     }
 }
\ No newline at end of file
@@ -1,6 +1,27 @@
+
+pub mod adapter;
+pub mod assets;
+pub mod error;
+pub mod preprocessor;
+pub mod shader;
+pub mod reflection;
 pub mod pipeline;
+pub mod pipeline_cache;
+pub mod compute_pipeline;
+pub mod hot_reload;
 pub mod mesh_builder;
 pub mod bind_group_layout;
 pub mod bind_group;
+pub mod resource_cache;
 pub mod material;
-pub mod ubo;
\ No newline at end of file
+pub mod bindless;
+pub mod ubo;
+pub mod immediates;
+pub mod readback;
+pub mod depth_buffer;
+pub mod multisample;
+pub mod render_targets;
+pub mod offscreen;
+pub mod recorder;
+#[cfg(test)]
+pub mod testing;
\ No newline at end of file
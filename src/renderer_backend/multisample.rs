@@ -0,0 +1,52 @@
+// Color target that gets rendered into with MSAA and resolved into the real target.
+pub struct MultisampleTarget {
+    #[allow(dead_code)]
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl MultisampleTarget {
+
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat, sample_count: u32, label: &str) -> Self {
+
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            label: Some(label),
+            view_formats: &[],
+        };
+        let texture = device.create_texture(&texture_descriptor);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view }
+    }
+}
+
+// Highest sample count up to `requested` that every format supports, the color format also has to resolve.
+pub fn supported_sample_count(adapter: &wgpu::Adapter, color_format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat, requested: u32) -> u32 {
+    let color_flags = adapter.get_texture_format_features(color_format).flags;
+    let depth_flags = adapter.get_texture_format_features(depth_format).flags;
+
+    let supported = |count: u32| {
+        count == 1 || (color_flags.sample_count_supported(count)
+            && color_flags.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE)
+            && depth_flags.sample_count_supported(count))
+    };
+
+    let sample_count = [16, 8, 4, 2, 1].into_iter()
+        .find(|count| *count <= requested && supported(*count))
+        .unwrap_or(1);
+    if sample_count != requested {
+        eprintln!("{}x MSAA isn't supported for {:?}, using {}x", requested, color_format, sample_count);
+    }
+
+    sample_count
+}
@@ -0,0 +1,49 @@
+use super::readback::Readback;
+
+pub struct Offscreen {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    readback: Readback,
+}
+
+impl Offscreen {
+
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, label: &str) -> Self {
+
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            label: Some(label),
+            view_formats: &[],
+        };
+        let texture = device.create_texture(&texture_descriptor);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let readback = Readback::new(device, width, height, Self::FORMAT);
+
+        Self { texture, view, readback }
+    }
+
+    // Copies the last rendered frame out and blocks until it has landed, then returns tightly packed RGBA8 rows.
+    // Only done on request, so frames nobody reads don't pay for the copy.
+    pub fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Vec<u8>, wgpu::BufferAsyncError> {
+        let command_encoder_descriptor = wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        };
+        let mut command_encoder = device.create_command_encoder(&command_encoder_descriptor);
+        self.readback.copy_from(&mut command_encoder, &self.texture);
+        queue.submit(std::iter::once(command_encoder.finish()));
+
+        self.readback.read(device)
+    }
+}
@@ -1,25 +1,126 @@
-use std::env::current_dir;
-use std::fs;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use super::bind_group_layout::Layout;
+use super::error::{self, PipelineError};
+use super::reflection::Reflection;
+use super::shader;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(dead_code)]
+pub enum BlendMode {
+    Replace,
+    Alpha,
+    PremultipliedAlpha,
+    Additive,
+    Multiply,
+    Custom(wgpu::BlendState),
+}
+
+impl BlendMode {
+    pub fn state(self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Replace => wgpu::BlendState::REPLACE,
+            BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+            BlendMode::PremultipliedAlpha => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            BlendMode::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+            BlendMode::Multiply => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Dst,
+                    dst_factor: wgpu::BlendFactor::Zero,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::DstAlpha,
+                    dst_factor: wgpu::BlendFactor::Zero,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+            BlendMode::Custom(state) => state,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorTarget {
+    pub format: wgpu::TextureFormat,
+    pub blend_mode: BlendMode,
+    pub write_mask: wgpu::ColorWrites,
+}
+
+impl ColorTarget {
+    pub fn new(format: wgpu::TextureFormat) -> Self {
+        Self {
+            format,
+            blend_mode: BlendMode::Replace,
+            write_mask: wgpu::ColorWrites::ALL,
+        }
+    }
+
+    // Replace leaves blending off, integer formats like an object ID buffer can't blend at all.
+    pub fn state(&self) -> wgpu::ColorTargetState {
+        wgpu::ColorTargetState {
+            format: self.format,
+            blend: (self.blend_mode != BlendMode::Replace).then(|| self.blend_mode.state()),
+            write_mask: self.write_mask,
+        }
+    }
+}
 
 pub struct Builder<'a> {
-    shader_filename: String,
+    shader: ShaderSetup,
     vertex_entry: String,
     fragment_entry: String,
-    pixel_format: wgpu::TextureFormat,
+    color_targets: Vec<ColorTarget>,
+    topology: wgpu::PrimitiveTopology,
+    strip_index_format: Option<wgpu::IndexFormat>,
+    front_face: wgpu::FrontFace,
+    cull_mode: Option<wgpu::Face>,
+    polygon_mode: wgpu::PolygonMode,
+    depth_format: Option<wgpu::TextureFormat>,
+    depth_compare: wgpu::CompareFunction,
+    depth_write: bool,
+    stencil: wgpu::StencilState,
+    sample_count: u32,
+    immediate_size: u32,
     vertex_buffer_layouts: Vec<wgpu::VertexBufferLayout<'a>>,
-    bind_group_layouts: Vec<&'a wgpu::BindGroupLayout>,
+    bind_group_layouts: Vec<&'a Layout>,
+    cache: Option<&'a wgpu::PipelineCache>,
     device: &'a wgpu::Device,
 }
 
 impl<'a> Builder<'a> {
     pub fn new(device:&'a wgpu::Device) -> Self {
         Self {
-            shader_filename: "dummy".to_string(),
+            shader: ShaderSetup::new(),
             vertex_entry: "dummy".to_string(),
             fragment_entry: "dummy".to_string(),
-            pixel_format: wgpu::TextureFormat::Rgba8Unorm,
+            color_targets: vec![ColorTarget::new(wgpu::TextureFormat::Rgba8Unorm)],
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            depth_format: None,
+            depth_compare: wgpu::CompareFunction::Less,
+            depth_write: true,
+            stencil: wgpu::StencilState::default(),
+            sample_count: 1,
+            immediate_size: 0,
             vertex_buffer_layouts: Vec::new(),
             bind_group_layouts: Vec::new(),
+            cache: None,
             device
         }
     }
@@ -35,94 +136,495 @@ impl<'a> Builder<'a> {
         self
     }
 
-    pub fn add_bind_group_layout(&mut self, layout: &'a wgpu::BindGroupLayout) -> &mut Self {
+    // Checked against the shader's bindings in build, see Reflection::check_bind_group_layout.
+    pub fn add_bind_group_layout(&mut self, layout: &'a Layout) -> &mut Self {
         self.bind_group_layouts.push(layout);
 
         self
     }
 
+    // Kept across builds like the device, see pipeline_cache.
+    pub fn set_cache(&mut self, cache: Option<&'a wgpu::PipelineCache>) -> &mut Self {
+        self.cache = cache;
+
+        self
+    }
+
+    // See ShaderSetup for the shader, its defines and constants.
     pub fn set_shader_module(&mut self, shader_filename: &str, vertex_entry: &str, fragment_entry: &str) -> &mut Self {
-        self.shader_filename = shader_filename.to_string();
+        self.shader.set_filename(shader_filename);
         self.vertex_entry = vertex_entry.to_string();
         self.fragment_entry = fragment_entry.to_string();
 
         self
     }
 
+    pub fn add_define(&mut self, name: &str) -> &mut Self {
+        self.shader.add_define(name);
+
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn set_constant(&mut self, name: &str, value: f64) -> &mut Self {
+        self.shader.set_constant(name, value);
+
+        self
+    }
+
+    // set_pixel_format, set_blend_mode and set_color_write_mask configure the first color target.
     pub fn set_pixel_format(&mut self, pixel_format: wgpu::TextureFormat) -> &mut Self {
-        self.pixel_format = pixel_format;
+        self.color_targets[0].format = pixel_format;
 
         self
     }
 
-    pub fn build(&mut self, label: &str) -> wgpu::RenderPipeline {
-        let mut filepath = current_dir().unwrap();
-        filepath.push("src/");
-        filepath.push(self.shader_filename.as_str());
-        let filepath = filepath.into_os_string().into_string().unwrap();
-        let source_code = fs::read_to_string(filepath).expect("Can't read source  code!");
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) -> &mut Self {
+        self.color_targets[0].blend_mode = blend_mode;
 
-        let shader_module_descriptor = wgpu::ShaderModuleDescriptor {
-            label: Some("Shader Module"),
-            source: wgpu::ShaderSource::Wgsl(source_code.into()),
-        };
-        let shader_module = self.device.create_shader_module(shader_module_descriptor);
+        self
+    }
 
-        let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
-            label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &self.bind_group_layouts,
-            immediate_size: 0,
+    #[allow(dead_code)]
+    pub fn set_color_write_mask(&mut self, color_write_mask: wgpu::ColorWrites) -> &mut Self {
+        self.color_targets[0].write_mask = color_write_mask;
+
+        self
+    }
+
+    // Further targets go to @location(1), @location(2), ... in the order they're added.
+    #[allow(dead_code)]
+    pub fn add_color_target(&mut self, color_target: ColorTarget) -> &mut Self {
+        self.color_targets.push(color_target);
+
+        self
+    }
+
+    // Replaces every target, including the first one.
+    #[allow(dead_code)]
+    pub fn set_color_targets(&mut self, color_targets: &[ColorTarget]) -> &mut Self {
+        assert!(!color_targets.is_empty(), "A pipeline needs at least one color target!");
+        self.color_targets = color_targets.to_vec();
+
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn set_topology(&mut self, topology: wgpu::PrimitiveTopology) -> &mut Self {
+        self.topology = topology;
+
+        self
+    }
+
+    // Only used with strip topologies, where it also enables primitive restart.
+    #[allow(dead_code)]
+    pub fn set_strip_index_format(&mut self, strip_index_format: Option<wgpu::IndexFormat>) -> &mut Self {
+        self.strip_index_format = strip_index_format;
+
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn set_front_face(&mut self, front_face: wgpu::FrontFace) -> &mut Self {
+        self.front_face = front_face;
+
+        self
+    }
+
+    pub fn set_cull_mode(&mut self, cull_mode: Option<wgpu::Face>) -> &mut Self {
+        self.cull_mode = cull_mode;
+
+        self
+    }
+
+    // Line and Point need device features, without them the pipeline falls back to Fill.
+    pub fn set_polygon_mode(&mut self, polygon_mode: wgpu::PolygonMode) -> &mut Self {
+        self.polygon_mode = polygon_mode;
+
+        self
+    }
+
+    fn supported_polygon_mode(&self) -> wgpu::PolygonMode {
+        let required_feature = match self.polygon_mode {
+            wgpu::PolygonMode::Fill => return wgpu::PolygonMode::Fill,
+            wgpu::PolygonMode::Line => wgpu::Features::POLYGON_MODE_LINE,
+            wgpu::PolygonMode::Point => wgpu::Features::POLYGON_MODE_POINT,
         };
-        let pipeline_layout = self.device.create_pipeline_layout(&pipeline_layout_descriptor);
 
-        let render_targets = [Some(wgpu::ColorTargetState {
-            format: self.pixel_format,
-            blend: Some(wgpu::BlendState::REPLACE),
-            write_mask: wgpu::ColorWrites::ALL,
-        })];
+        if self.device.features().contains(required_feature) {
+            self.polygon_mode
+        } else {
+            eprintln!("Device doesn't support {:?}, falling back to PolygonMode::Fill", required_feature);
+            wgpu::PolygonMode::Fill
+        }
+    }
+
+    // Pipelines without a depth format don't test or write depth at all.
+    pub fn set_depth_format(&mut self, depth_format: wgpu::TextureFormat) -> &mut Self {
+        self.depth_format = Some(depth_format);
+
+        self
+    }
+
+    pub fn set_depth_compare(&mut self, depth_compare: wgpu::CompareFunction) -> &mut Self {
+        self.depth_compare = depth_compare;
+
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn set_depth_write(&mut self, depth_write: bool) -> &mut Self {
+        self.depth_write = depth_write;
+
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn set_stencil(&mut self, stencil: wgpu::StencilState) -> &mut Self {
+        self.stencil = stencil;
+
+        self
+    }
+
+    // Has to match the attachments, see multisample::supported_sample_count.
+    pub fn set_sample_count(&mut self, sample_count: u32) -> &mut Self {
+        self.sample_count = sample_count;
+
+        self
+    }
+
+    // Bytes of immediate data the shader's var<immediate> takes, see immediates::Immediate.
+    // Anything above 0 needs Features::IMMEDIATES.
+    pub fn set_immediate_size(&mut self, immediate_size: u32) -> &mut Self {
+        self.immediate_size = immediate_size;
+
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn shader_filename(&self) -> &str {
+        &self.shader.filename
+    }
+
+    #[allow(dead_code)]
+    pub fn specialization(&self) -> Specialization {
+        self.shader.specialization()
+    }
+
+    pub fn build(&mut self, label: &str) -> Result<wgpu::RenderPipeline, PipelineError> {
+        let result = self.create(label);
+
+        self.reset();
+
+        result
+    }
+
+    fn create(&self, label: &str) -> Result<wgpu::RenderPipeline, PipelineError> {
+        let check = |reflection: &Reflection| reflection.check_vertex_buffers(&self.vertex_entry, &self.vertex_buffer_layouts);
+        self.shader.create_pipeline(self.device, label, &self.bind_group_layouts, self.immediate_size, check,
+            |shader_module, constants, pipeline_layout| self.create_render_pipeline(label, shader_module, constants, pipeline_layout))
+    }
+
+    fn create_render_pipeline(&self, label: &str, shader_module: &wgpu::ShaderModule, constants: &[(&str, f64)],
+        pipeline_layout: &wgpu::PipelineLayout) -> wgpu::RenderPipeline {
+
+        let render_targets: Vec<Option<wgpu::ColorTargetState>> = self.color_targets.iter()
+            .map(|color_target| Some(color_target.state()))
+            .collect();
 
         let render_pipeline_descriptor = wgpu::RenderPipelineDescriptor {
             label: Some(label),
-            layout: Some(&pipeline_layout),
+            layout: Some(pipeline_layout),
 
             vertex: wgpu::VertexState {
-                module: &shader_module,
+                module: shader_module,
                 entry_point: Some(&self.vertex_entry),
-                compilation_options: Default::default(),
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants,
+                    ..Default::default()
+                },
                 buffers: &self.vertex_buffer_layouts,
             },
 
             primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
+                topology: self.topology,
+                strip_index_format: self.strip_index_format.filter(|_| self.topology.is_strip()),
+                front_face: self.front_face,
+                cull_mode: self.cull_mode,
+                polygon_mode: self.supported_polygon_mode(),
                 unclipped_depth: false,
                 conservative: false,
             },
 
             fragment: Some(wgpu::FragmentState {
-                module: &shader_module,
+                module: shader_module,
                 entry_point: Some(&self.fragment_entry),
-                compilation_options: Default::default(),
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants,
+                    ..Default::default()
+                },
                 targets: &render_targets,
             }),
 
-            depth_stencil: None,
+            depth_stencil: self.depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: self.depth_write,
+                depth_compare: self.depth_compare,
+                stencil: self.stencil.clone(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: self.sample_count,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
             multiview_mask: None,
-            cache: None,
+            cache: self.cache,
         };
 
-        let pipeline = self.device.create_render_pipeline(&render_pipeline_descriptor);
+        self.device.create_render_pipeline(&render_pipeline_descriptor)
+    }
+}
 
-        self.reset();
+// The shader half of a pipeline, shared by this Builder and compute_pipeline::Builder.
+pub struct ShaderSetup {
+    filename: String,
+    defines: Vec<String>,
+    constants: Vec<(String, f64)>,
+}
 
-        pipeline
+impl ShaderSetup {
+
+    pub fn new() -> Self {
+        Self { filename: "dummy".to_string(), defines: Vec::new(), constants: Vec::new() }
+    }
+
+    // An asset name like "shaders/shader.wgsl", found through assets::locator().
+    pub fn set_filename(&mut self, filename: &str) {
+        self.filename = filename.to_string();
+    }
+
+    // Sets a flag for #ifdef in the shader, see preprocessor.
+    pub fn add_define(&mut self, name: &str) {
+        self.defines.push(name.to_string());
+    }
+
+    // Sets a WGSL `override` constant, bools take 0.0 or 1.0.
+    pub fn set_constant(&mut self, name: &str, value: f64) {
+        match self.constants.iter_mut().find(|(constant, _)| constant == name) {
+            Some((_, old_value)) => *old_value = value,
+            None => self.constants.push((name.to_string(), value)),
+        }
+    }
+
+    pub fn specialization(&self) -> Specialization {
+        let mut defines = self.defines.clone();
+        defines.sort();
+        defines.dedup();
+        let mut constants: Vec<(String, u64)> = self.constants.iter()
+            .map(|(name, value)| (name.clone(), value.to_bits()))
+            .collect();
+        constants.sort();
+
+        Specialization { defines, constants }
     }
-}
\ No newline at end of file
+
+    // Checks the layouts against the shader, plus whatever `check` adds, then hands the module,
+    // constants and pipeline layout to `create`. Validation errors from `create` become
+    // PipelineError::Pipeline instead of going to the device's error handler.
+    pub fn create_pipeline<P>(&self, device: &wgpu::Device, label: &str, bind_group_layouts: &[&Layout], immediate_size: u32,
+        check: impl FnOnce(&Reflection) -> Result<(), PipelineError>,
+        create: impl FnOnce(&wgpu::ShaderModule, &[(&str, f64)], &wgpu::PipelineLayout) -> P) -> Result<P, PipelineError> {
+
+        let shader = shader::parse(&self.filename, &self.defines)?;
+        let reflection = Reflection::new(&shader)?;
+        check(&reflection)?;
+        for (group, layout) in bind_group_layouts.iter().enumerate() {
+            reflection.check_bind_group_layout(group as u32, layout.entries())?;
+        }
+        let shader_module = shader.create_module(device)?;
+        let constants: Vec<(&str, f64)> = self.constants.iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect();
+
+        let error_scope = device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let bind_group_layouts: Vec<&wgpu::BindGroupLayout> = bind_group_layouts.iter()
+            .map(|layout| &***layout)
+            .collect();
+        let pipeline_layout_label = format!("{} Layout", label);
+        let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
+            label: Some(&pipeline_layout_label),
+            bind_group_layouts: &bind_group_layouts,
+            immediate_size,
+        };
+        let pipeline_layout = device.create_pipeline_layout(&pipeline_layout_descriptor);
+
+        let pipeline = create(&shader_module, &constants, &pipeline_layout);
+
+        match pollster::block_on(error_scope.pop()) {
+            Some(e) => Err(PipelineError::Pipeline { label: label.to_string(), message: error::describe(&e) }),
+            None => Ok(pipeline),
+        }
+    }
+}
+
+// The defines and override constants a pipeline was built with.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[allow(dead_code)]
+pub struct Specialization {
+    defines: Vec<String>,
+    constants: Vec<(String, u64)>,
+}
+
+// Pipelines of one builder setup, one for every set of defines and override constants.
+// The rest of the builder state isn't part of the key, use one Variants per setup.
+#[allow(dead_code)]
+pub struct Variants {
+    pipelines: HashMap<Specialization, wgpu::RenderPipeline>,
+}
+
+#[allow(dead_code)]
+impl Variants {
+
+    pub fn new() -> Self {
+        Self { pipelines: HashMap::new() }
+    }
+
+    // Builds on the first request for the builder's current specialization and reuses that
+    // pipeline afterwards. The builder is reset either way.
+    pub fn get_or_build(&mut self, builder: &mut Builder, label: &str) -> Result<&wgpu::RenderPipeline, PipelineError> {
+        match self.pipelines.entry(builder.specialization()) {
+            Entry::Occupied(entry) => {
+                builder.reset();
+                Ok(entry.into_mut())
+            }
+            Entry::Vacant(entry) => Ok(entry.insert(builder.build(label)?)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    // E.g. after the shader changed.
+    pub fn clear(&mut self) {
+        self.pipelines.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{BlendMode, Builder, ColorTarget, Variants};
+    use crate::renderer_backend::bind_group_layout;
+    use crate::renderer_backend::error::{PipelineError, SourceLocation};
+    use crate::renderer_backend::render_targets::RenderTargets;
+    use crate::renderer_backend::testing;
+
+    #[test]
+    fn blend_modes_map_to_blend_states() {
+        assert_eq!(BlendMode::Alpha.state(), wgpu::BlendState::ALPHA_BLENDING);
+        assert_eq!(BlendMode::Additive.state().color.dst_factor, wgpu::BlendFactor::One);
+        assert_eq!(BlendMode::Multiply.state().color.src_factor, wgpu::BlendFactor::Dst);
+        assert_eq!(BlendMode::Custom(wgpu::BlendState::REPLACE).state(), wgpu::BlendState::REPLACE);
+    }
+    #[test]
+    fn replace_leaves_blending_off() {
+        let id_target = ColorTarget::new(wgpu::TextureFormat::R32Uint);
+        assert_eq!(id_target.state().blend, None);
+
+        let color_target = ColorTarget { blend_mode: BlendMode::Alpha, ..ColorTarget::new(wgpu::TextureFormat::Rgba8Unorm) };
+        assert_eq!(color_target.state().blend, Some(wgpu::BlendState::ALPHA_BLENDING));
+    }
+
+    // Written to target/shader_errors, the absolute path skips the asset search.
+    fn test_shader(name: &str) -> String {
+        let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/shader_errors");
+        std::fs::create_dir_all(&directory).unwrap();
+
+        directory.join(name).to_str().unwrap().to_string()
+    }
+
+    fn build_shader(device: &wgpu::Device, name: &str, source: Option<&str>) -> Result<wgpu::RenderPipeline, PipelineError> {
+        let path = test_shader(name);
+        match source {
+            Some(source) => std::fs::write(&path, source).unwrap(),
+            None => { let _ = std::fs::remove_file(&path); }
+        }
+
+        let mut builder = Builder::new(device);
+        builder.set_shader_module(&path, "vs_main", "fs_main");
+        builder.build("Broken Pipeline")
+    }
+
+    const VERTEX_SHADER: &str = "@vertex fn vs_main() -> @builtin(position) vec4<f32> { return vec4<f32>(0.0); }\n";
+
+    #[test]
+    fn shader_errors_are_reported_by_kind() {
+        let (device, _queue) = testing::device();
+
+        let error = build_shader(&device, "missing.wgsl", None).unwrap_err();
+        assert!(matches!(error, PipelineError::MissingFile { .. }), "{}", error);
+
+        let source = format!("{}@fragment fn fs_main() -> @location(0) vec4<f32> {{ return oops; }}", VERTEX_SHADER);
+        let error = build_shader(&device, "parse.wgsl", Some(&source)).unwrap_err();
+        assert!(matches!(error, PipelineError::Parse { location: Some(SourceLocation { line: 2, column: 59 }), .. }), "{}", error);
+
+        let source = format!("{}@fragment fn fs_main() -> @location(0) bool {{ return true; }}", VERTEX_SHADER);
+        let error = build_shader(&device, "validation.wgsl", Some(&source)).unwrap_err();
+        assert!(matches!(error, PipelineError::Validation { .. }), "{}", error);
+
+        // Lines in included files are reported against those files
+        build_shader(&device, "included.wgsl", Some("\n\nfn broken() -> f32 { return oops; }\n")).unwrap_err();
+        let source = format!("{}#include \"included.wgsl\"\n@fragment fn fs_main() -> @location(0) vec4<f32> {{ return vec4<f32>(broken()); }}", VERTEX_SHADER);
+        let error = build_shader(&device, "includes.wgsl", Some(&source)).unwrap_err();
+        assert!(matches!(&error, PipelineError::Parse { path, location: Some(SourceLocation { line: 3, column: 29 }), .. }
+            if path.ends_with("included.wgsl")), "{}", error);
+
+        // Group 0 isn't in the (empty) pipeline layout
+        let source = format!("{}@group(0) @binding(0) var<uniform> tint: vec4<f32>;\n\
+            @fragment fn fs_main() -> @location(0) vec4<f32> {{ return tint; }}", VERTEX_SHADER);
+        let error = build_shader(&device, "layout.wgsl", Some(&source)).unwrap_err();
+        assert!(matches!(error, PipelineError::Pipeline { .. }), "{}", error);
+
+        // A hand built layout with the wrong binding type is caught before wgpu sees it
+        let layout = bind_group_layout::Builder::new(&device)
+            .add_storage_buffer(wgpu::ShaderStages::FRAGMENT, true)
+            .build("Wrong Layout");
+        let mut builder = Builder::new(&device);
+        builder.set_shader_module(&test_shader("layout.wgsl"), "vs_main", "fs_main")
+            .add_bind_group_layout(&layout);
+        let error = builder.build("Wrong Layout Pipeline").unwrap_err();
+        assert!(matches!(error, PipelineError::Binding { group: 0, binding: 0, .. }), "{}", error);
+    }
+
+    #[test]
+    fn variants_are_cached_per_constant_set() {
+        let (device, queue) = testing::device();
+
+        let mut targets = RenderTargets::new(4, 4);
+        targets.add_buffer(&device, wgpu::TextureFormat::Rgba8Unorm, wgpu::Color::BLACK, "Color Buffer");
+
+        let mut variants = Variants::new();
+        let mut builder = Builder::new(&device);
+        builder.set_shader_module("shaders/constants_test.wgsl", "vs_main", "fs_main")
+            .set_pixel_format(wgpu::TextureFormat::Rgba8Unorm)
+            .set_constant("RED", 1.0);
+        let red = variants.get_or_build(&mut builder, "Red Pipeline").unwrap().clone();
+
+        builder.set_constant("USE_BLUE", 1.0);
+        let magenta = variants.get_or_build(&mut builder, "Magenta Pipeline").unwrap().clone();
+        let magenta_again = variants.get_or_build(&mut builder, "Magenta Pipeline").unwrap().clone();
+
+        assert_eq!(variants.len(), 2);
+        assert_eq!(magenta, magenta_again);
+        assert_eq!(testing::render_fullscreen(&device, &queue, &red, &targets)[0][..4], [255, 0, 0, 255]);
+        assert_eq!(testing::render_fullscreen(&device, &queue, &magenta, &targets)[0][..4], [255, 0, 255, 255]);
+    }
+}
@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+
+// Compiled pipelines kept on disk between runs, so drivers don't compile every shader
+// again at startup. Needs Features::PIPELINE_CACHE, which only Vulkan has so far.
+pub struct PipelineCache {
+    pub cache: wgpu::PipelineCache,
+    path: PathBuf,
+}
+
+impl PipelineCache {
+
+    // None without the feature or a cache directory. A missing, stale or foreign file
+    // just starts an empty cache.
+    pub fn load(device: &wgpu::Device, adapter_info: &wgpu::AdapterInfo) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            return None;
+        }
+        let path = file_path(&cache_directory()?, adapter_info)?;
+        let data = std::fs::read(&path).ok();
+
+        let pipeline_cache_descriptor = wgpu::PipelineCacheDescriptor {
+            label: Some("Pipeline Cache"),
+            data: data.as_deref(),
+            fallback: true,
+        };
+        // Safety: the file is only ever written by save, with data wgpu gave us for this
+        // adapter, and wgpu checks the header before handing it to the driver
+        let cache = unsafe { device.create_pipeline_cache(&pipeline_cache_descriptor) };
+
+        Some(Self { cache, path })
+    }
+
+    // Writes through a temporary file so a crash can't leave half a cache behind.
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(data) = self.cache.get_data() else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(self.path.parent().unwrap())?;
+        let temporary = self.path.with_extension("tmp");
+        std::fs::write(&temporary, &data)?;
+        std::fs::rename(&temporary, &self.path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+// One file per driver, the key names the backend, vendor and device.
+fn file_path(directory: &Path, adapter_info: &wgpu::AdapterInfo) -> Option<PathBuf> {
+    let key = wgpu::util::pipeline_cache_key(adapter_info)?;
+
+    Some(directory.join("gigd-wgpu").join(key))
+}
+
+// The platform's per-user cache directory.
+fn cache_directory() -> Option<PathBuf> {
+    let env = |name| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+
+    if cfg!(target_os = "windows") {
+        env("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        env("HOME").map(|home| home.join("Library/Caches"))
+    } else {
+        env("XDG_CACHE_HOME").or_else(|| env("HOME").map(|home| home.join(".cache")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::file_path;
+
+    #[test]
+    fn files_are_keyed_by_adapter() {
+        let mut adapter_info = wgpu::AdapterInfo {
+            name: "Test GPU".to_string(),
+            vendor: 0x10de,
+            device: 0x2684,
+            device_type: wgpu::DeviceType::DiscreteGpu,
+            device_pci_bus_id: String::new(),
+            driver: String::new(),
+            driver_info: String::new(),
+            backend: wgpu::Backend::Vulkan,
+            subgroup_min_size: 4,
+            subgroup_max_size: 128,
+            transient_saves_memory: false,
+        };
+        let path = file_path(Path::new("/cache"), &adapter_info).unwrap();
+        assert!(path.starts_with("/cache/gigd-wgpu"));
+        assert!(path.to_str().unwrap().ends_with(&format!("{}_{}", 0x10de, 0x2684)));
+
+        adapter_info.backend = wgpu::Backend::Gl;
+        assert!(file_path(Path::new("/cache"), &adapter_info).is_none());
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use super::assets::{self, Locator};
+use super::error::PipelineError;
+
+// Files are asset names, see assets. Supported directives, each on its own line:
+//   #include "common.wgsl"   relative to the including file, every file is included once
+//   #define NAME             a flag for #ifdef, the builder can set them as well
+//   #ifdef NAME / #ifndef NAME / #else / #endif
+#[derive(Debug)]
+pub struct Output {
+    pub code: String,
+    pub source_map: SourceMap,
+}
+
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    // Where each file was loaded from, on disk or embedded
+    files: Vec<PathBuf>,
+    // File index and 1-based line for every line of the output
+    lines: Vec<(usize, u32)>,
+}
+
+impl SourceMap {
+    // Original file and line of a 1-based line in the output.
+    pub fn resolve(&self, line: u32) -> Option<(&Path, u32)> {
+        let (file, line) = *self.lines.get(line.checked_sub(1)? as usize)?;
+        Some((&self.files[file], line))
+    }
+
+    // Every file that ended up in the output, the root file first.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+}
+
+pub fn process(name: impl AsRef<Path>, defines: &[String]) -> Result<Output, PipelineError> {
+    let mut preprocessor = Preprocessor {
+        defines: defines.iter().cloned().collect(),
+        included: Vec::new(),
+        code: String::new(),
+        source_map: SourceMap::default(),
+    };
+    preprocessor.include(&assets::normalize(name))?;
+
+    Ok(Output { code: preprocessor.code, source_map: preprocessor.source_map })
+}
+
+// Asset names of `name` and the files it includes, directly or not, whether their #include is active or not.
+pub fn dependencies(locator: &Locator, name: impl AsRef<Path>) -> Vec<PathBuf> {
+    let mut files = vec![assets::normalize(name)];
+    let mut i = 0;
+    while i < files.len() {
+        let asset = locator.load(&files[i]);
+        let source = asset.as_ref().ok().and_then(|asset| asset.text().ok()).unwrap_or_default();
+        for line in source.lines() {
+            if let Some(("include", argument)) = directive(line)
+                && let Some(filename) = quoted(argument) {
+                let include = assets::normalize(files[i].parent().unwrap().join(filename));
+                if !files.contains(&include) {
+                    files.push(include);
+                }
+            }
+        }
+        i += 1;
+    }
+
+    files
+}
+
+struct Condition {
+    active: bool,
+    has_else: bool,
+}
+
+struct Preprocessor {
+    defines: HashSet<String>,
+    // Normalized names of the files included so far
+    included: Vec<PathBuf>,
+    code: String,
+    source_map: SourceMap,
+}
+
+impl Preprocessor {
+
+    fn include(&mut self, asset_name: &Path) -> Result<(), PipelineError> {
+        if self.included.iter().any(|included| included == asset_name) {
+            return Ok(());
+        }
+        let missing_file = |source| PipelineError::MissingFile { path: asset_name.to_path_buf(), source };
+        let asset = assets::load(asset_name).map_err(missing_file)?;
+        let source = asset.text().map_err(missing_file)?;
+        let path = asset.path.clone();
+
+        let file = self.source_map.files.len();
+        self.included.push(asset_name.to_path_buf());
+        self.source_map.files.push(path.clone());
+
+        let mut conditions: Vec<Condition> = Vec::new();
+        let mut line_number = 0;
+        for line in source.lines() {
+            line_number += 1;
+            let error = |message: &str| PipelineError::Preprocess { path: path.clone(), line: line_number, message: message.to_string() };
+            let active = conditions.iter().all(|condition| condition.active);
+
+            let Some((name, argument)) = directive(line) else {
+                if active {
+                    self.code.push_str(line);
+                    self.code.push('\n');
+                    self.source_map.lines.push((file, line_number));
+                }
+                continue;
+            };
+
+            match name {
+                "include" if active => {
+                    let filename = quoted(argument).ok_or_else(|| error("#include needs a quoted filename"))?;
+                    self.include(&assets::normalize(asset_name.parent().unwrap().join(filename)))?;
+                }
+                "define" if active => {
+                    if argument.is_empty() {
+                        return Err(error("#define needs a name"));
+                    }
+                    self.defines.insert(argument.to_string());
+                }
+                "include" | "define" => {}
+                "ifdef" | "ifndef" => {
+                    let defined = self.defines.contains(argument);
+                    conditions.push(Condition { active: defined == (name == "ifdef"), has_else: false });
+                }
+                "else" => {
+                    let condition = conditions.last_mut().ok_or_else(|| error("#else without #ifdef"))?;
+                    if condition.has_else {
+                        return Err(error("Second #else for the same #ifdef"));
+                    }
+                    condition.active = !condition.active;
+                    condition.has_else = true;
+                }
+                "endif" => {
+                    conditions.pop().ok_or_else(|| error("#endif without #ifdef"))?;
+                }
+                _ => return Err(error(&format!("Unknown directive #{}", name))),
+            }
+        }
+
+        if !conditions.is_empty() {
+            return Err(PipelineError::Preprocess { path, line: line_number, message: "#ifdef without #endif".to_string() });
+        }
+
+        Ok(())
+    }
+}
+
+// Splits "#name argument" into its parts, None for regular WGSL lines.
+fn directive(line: &str) -> Option<(&str, &str)> {
+    let directive = line.trim().strip_prefix('#')?;
+    let (name, argument) = directive.split_once(char::is_whitespace).unwrap_or((directive, ""));
+
+    Some((name, argument.trim()))
+}
+
+fn quoted(argument: &str) -> Option<&str> {
+    argument.strip_prefix('"')?.strip_suffix('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{dependencies, process};
+    use crate::renderer_backend::assets::Locator;
+    use crate::renderer_backend::error::PipelineError;
+
+    fn write_files(directory: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/preprocessor").join(directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        for (name, source) in files {
+            std::fs::write(directory.join(name), source).unwrap();
+        }
+
+        directory
+    }
+
+    #[test]
+    fn includes_once_and_maps_lines_back() {
+        let directory = write_files("include", &[
+            ("main.wgsl", "#include \"common.wgsl\"\n#include \"common.wgsl\"\nfn main() {}\n"),
+            ("common.wgsl", "// common\nstruct A { x: f32 };\n"),
+        ]);
+
+        let output = process(directory.join("main.wgsl"), &[]).unwrap();
+        assert_eq!(output.code, "// common\nstruct A { x: f32 };\nfn main() {}\n");
+
+        let (file, line) = output.source_map.resolve(2).unwrap();
+        assert!(file.ends_with("common.wgsl"));
+        assert_eq!(line, 2);
+        let (file, line) = output.source_map.resolve(3).unwrap();
+        assert!(file.ends_with("main.wgsl"));
+        assert_eq!(line, 3);
+
+        assert_eq!(dependencies(&Locator::new(&directory), "main.wgsl").len(), 2);
+    }
+
+    #[test]
+    fn ifdef_follows_defines() {
+        let directory = write_files("ifdef", &[
+            ("main.wgsl", "#ifdef A\na\n#else\nnot a\n#endif\n#define B\n#ifndef B\nnot b\n#endif\n"),
+        ]);
+        let path = directory.join("main.wgsl");
+
+        assert_eq!(process(&path, &[]).unwrap().code, "not a\n");
+        assert_eq!(process(&path, &["A".to_string()]).unwrap().code, "a\n");
+    }
+
+    #[test]
+    fn reports_unbalanced_conditions() {
+        let directory = write_files("unbalanced", &[
+            ("endif.wgsl", "fn main() {}\n#endif\n"),
+            ("ifdef.wgsl", "#ifdef A\n"),
+        ]);
+
+        let error = process(directory.join("endif.wgsl"), &[]).unwrap_err();
+        assert!(matches!(error, PipelineError::Preprocess { line: 2, .. }), "{}", error);
+        let error = process(directory.join("ifdef.wgsl"), &[]).unwrap_err();
+        assert!(matches!(error, PipelineError::Preprocess { line: 1, .. }), "{}", error);
+    }
+}
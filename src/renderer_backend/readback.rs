@@ -0,0 +1,117 @@
+use std::cell::RefCell;
+use std::sync::mpsc;
+
+pub struct Readback {
+    buffer: wgpu::Buffer,
+    format: wgpu::TextureFormat,
+    padded_bytes_per_row: u32,
+    width: u32,
+    height: u32,
+    // map_async reports back on whichever thread polls the device
+    sender: mpsc::Sender<Result<(), wgpu::BufferAsyncError>>,
+    receiver: mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+    status: RefCell<Option<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+impl Readback {
+
+    pub fn supports(format: wgpu::TextureFormat) -> bool {
+        matches!(format,
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb |
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb)
+    }
+
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+
+        // Rows copied into a buffer have to be aligned to COPY_BYTES_PER_ROW_ALIGNMENT
+        let padded_bytes_per_row = (4 * width).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer_descriptor = wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        };
+        let buffer = device.create_buffer(&buffer_descriptor);
+
+        let (sender, receiver) = mpsc::channel();
+
+        Self { buffer, format, padded_bytes_per_row, width, height, sender, receiver, status: RefCell::new(None) }
+    }
+
+    pub fn copy_from(&self, command_encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) {
+        command_encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &self.buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            });
+    }
+
+    // Blocks until the last copy has landed, then returns tightly packed RGBA8 rows.
+    pub fn read(&self, device: &wgpu::Device) -> Result<Vec<u8>, wgpu::BufferAsyncError> {
+        self.map();
+        device.poll(wgpu::PollType::wait_indefinitely()).unwrap();
+
+        self.read_mapped()
+    }
+
+    // Starts mapping the buffer without waiting, call this after the copy has been submitted.
+    pub fn map(&self) {
+        let sender = self.sender.clone();
+        self.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            // Only fails if the Readback is gone, and then nobody wants the result
+            let _ = sender.send(result);
+        });
+    }
+
+    // True once the map has finished, read_mapped tells whether it worked.
+    pub fn is_ready(&self) -> bool {
+        let mut status = self.status.borrow_mut();
+        if status.is_none() {
+            *status = self.receiver.try_recv().ok();
+        }
+
+        status.is_some()
+    }
+
+    // Returns tightly packed RGBA8 rows and unmaps the buffer so it can be reused.
+    pub fn read_mapped(&self) -> Result<Vec<u8>, wgpu::BufferAsyncError> {
+        let status = self.status.take().or_else(|| self.receiver.try_recv().ok());
+        status.expect("read_mapped needs a finished map, see is_ready")?;
+
+        let unpadded_bytes_per_row = (4 * self.width) as usize;
+        let mut pixels = Vec::with_capacity(unpadded_bytes_per_row * self.height as usize);
+        {
+            let data = self.buffer.slice(..).get_mapped_range();
+            for row in data.chunks(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row]);
+            }
+        }
+        self.buffer.unmap();
+
+        // sRGB and plain unorm targets both hold the values that end up on screen,
+        // so only the channel order needs fixing up.
+        if matches!(self.format, wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        Ok(pixels)
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+use super::readback::Readback;
+
+#[derive(Clone)]
+pub enum Output {
+    // frame_00000.png, frame_00001.png, ... inside the given directory
+    PngSequence(PathBuf),
+    Gif(PathBuf),
+    Apng(PathBuf),
+}
+
+impl Output {
+    // Picks the encoder from the file extension, anything without one is a directory for a PNG sequence.
+    pub fn from_path(path: &str) -> Self {
+        let path = PathBuf::from(path);
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gif") => Output::Gif(path),
+            Some("png") | Some("apng") => Output::Apng(path),
+            _ => Output::PngSequence(path),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            Output::PngSequence(path) | Output::Gif(path) | Output::Apng(path) => path,
+        }
+    }
+}
+
+// Records a fixed number of frames. Copies are mapped asynchronously and handed to an
+// encoder thread once the GPU is done with them, so the render loop never waits on readback.
+pub struct Recorder {
+    output: Output,
+    fps: u32,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    frames_left: u32,
+    in_flight: VecDeque<Readback>,
+    free: Vec<Readback>,
+    sender: Option<mpsc::Sender<Vec<u8>>>,
+    worker: Option<JoinHandle<Result<(), String>>>,
+    // A frame that couldn't be read back ends the recording, finish() reports it
+    failed: Option<wgpu::BufferAsyncError>,
+}
+
+impl Recorder {
+
+    pub fn new(output: Output, frame_count: u32, fps: u32, width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+
+        let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+
+        let worker = {
+            let output = output.clone();
+            std::thread::spawn(move || encode(output, receiver, frame_count, fps, width, height))
+        };
+
+        Self {
+            output,
+            fps,
+            width,
+            height,
+            format,
+            frames_left: frame_count,
+            in_flight: VecDeque::new(),
+            free: Vec::new(),
+            sender: Some(sender),
+            worker: Some(worker),
+            failed: None,
+        }
+    }
+
+    // Simulated time between two recorded frames.
+    pub fn timestep(&self) -> f32 {
+        1.0 / self.fps as f32
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.frames_left > 0
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.frames_left == 0 && self.in_flight.is_empty()
+    }
+
+    // Hands out a buffer for the next frame, record a copy into it and pass it back to `submitted`.
+    pub fn next_readback(&mut self, device: &wgpu::Device) -> Readback {
+        self.free.pop().unwrap_or_else(|| Readback::new(device, self.width, self.height, self.format))
+    }
+
+    pub fn submitted(&mut self, readback: Readback) {
+        readback.map();
+        self.in_flight.push_back(readback);
+        self.frames_left -= 1;
+    }
+
+    // Sends every frame the GPU has finished with to the encoder, in order, without blocking.
+    pub fn poll(&mut self, device: &wgpu::Device) {
+        device.poll(wgpu::PollType::Poll).unwrap();
+
+        while self.in_flight.front().is_some_and(|readback| readback.is_ready()) {
+            let readback = self.in_flight.pop_front().unwrap();
+            match readback.read_mapped() {
+                Ok(pixels) => if let Some(sender) = &self.sender {
+                    // A send error means the encoder already failed, finish() reports it
+                    let _ = sender.send(pixels);
+                }
+                Err(e) => {
+                    self.failed.get_or_insert(e);
+                    self.sender = None;
+                }
+            }
+            self.free.push(readback);
+        }
+    }
+
+    // Waits for the outstanding frames and the encoder.
+    pub fn finish(mut self, device: &wgpu::Device) -> Result<PathBuf, String> {
+        while !self.in_flight.is_empty() {
+            device.poll(wgpu::PollType::wait_indefinitely()).unwrap();
+            self.poll(device);
+        }
+
+        self.sender = None;
+        let result = self.worker.take().unwrap().join()
+            .unwrap_or_else(|_| Err("Encoder thread panicked".to_string()));
+
+        if let Some(e) = self.failed {
+            return Err(format!("Can't read a frame back: {}", e));
+        }
+
+        result.map(|_| self.output.path().to_path_buf())
+    }
+}
+
+fn encode(output: Output, receiver: mpsc::Receiver<Vec<u8>>, frame_count: u32, fps: u32, width: u32, height: u32) -> Result<(), String> {
+    match output {
+        Output::PngSequence(directory) => {
+            std::fs::create_dir_all(&directory).map_err(|e| e.to_string())?;
+            for (i, pixels) in receiver.iter().enumerate() {
+                let filename = directory.join(format!("frame_{:05}.png", i));
+                image::save_buffer(&filename, &pixels, width, height, image::ExtendedColorType::Rgba8)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+
+        Output::Gif(filename) => {
+            use image::codecs::gif::{GifEncoder, Repeat};
+
+            let file = File::create(&filename).map_err(|e| e.to_string())?;
+            let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), 10);
+            encoder.set_repeat(Repeat::Infinite).map_err(|e| e.to_string())?;
+            for pixels in receiver.iter() {
+                let buffer = image::RgbaImage::from_raw(width, height, pixels).unwrap();
+                let delay = image::Delay::from_numer_denom_ms(1000, fps);
+                encoder.encode_frame(image::Frame::from_parts(buffer, 0, 0, delay))
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+
+        Output::Apng(filename) => {
+            let fps = u16::try_from(fps).map_err(|_| format!("{} fps is too many for APNG", fps))?;
+
+            let file = File::create(&filename).map_err(|e| e.to_string())?;
+            let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(frame_count, 0).map_err(|e| e.to_string())?;
+            encoder.set_frame_delay(1, fps).map_err(|e| e.to_string())?;
+
+            let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+            let mut frames = 0;
+            for pixels in receiver.iter() {
+                writer.write_image_data(&pixels).map_err(|e| e.to_string())?;
+                frames += 1;
+            }
+            writer.finish().map_err(|e| e.to_string())?;
+
+            if frames == 0 {
+                let _ = std::fs::remove_file(&filename);
+                return Err("No frames were recorded".to_string());
+            }
+            // The window closed or resized before all frames came in
+            if frames < frame_count {
+                set_apng_frame_count(&filename, frames).map_err(|e| e.to_string())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Rewrites num_frames in the acTL chunk, which sits in front of the image data.
+fn set_apng_frame_count(filename: &Path, frames: u32) -> std::io::Result<()> {
+    let mut file = File::options().read(true).write(true).open(filename)?;
+    let mut offset = 8;
+    loop {
+        let mut header = [0; 8];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+        let length = u32::from_be_bytes(header[..4].try_into().unwrap()) as u64;
+        if &header[4..] == b"acTL" {
+            let mut data = [0; 8];
+            file.read_exact(&mut data)?;
+            data[..4].copy_from_slice(&frames.to_be_bytes());
+
+            let mut hasher = crc32fast::Hasher::new();
+            hasher.update(b"acTL");
+            hasher.update(&data);
+
+            file.seek(SeekFrom::Start(offset + 8))?;
+            file.write_all(&data)?;
+            return file.write_all(&hasher.finalize().to_be_bytes());
+        }
+        if &header[4..] == b"IDAT" {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "APNG has no acTL chunk"));
+        }
+        // length, type, data and CRC
+        offset += 12 + length;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::mpsc;
+
+    use super::{Output, encode};
+
+    #[test]
+    fn output_follows_extension() {
+        assert!(matches!(Output::from_path("clip.gif"), Output::Gif(_)));
+        assert!(matches!(Output::from_path("clip.png"), Output::Apng(_)));
+        assert!(matches!(Output::from_path("clip.apng"), Output::Apng(_)));
+        assert!(matches!(Output::from_path("frames"), Output::PngSequence(_)));
+    }
+
+    #[test]
+    fn apng_stopped_early_has_the_recorded_frame_count() {
+        let filename = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/recorder/stopped_early.png");
+        std::fs::create_dir_all(filename.parent().unwrap()).unwrap();
+
+        // 2 of the 120 requested frames before the recording stops
+        let (sender, receiver) = mpsc::channel();
+        sender.send(vec![255; 4 * 4 * 4]).unwrap();
+        sender.send(vec![0; 4 * 4 * 4]).unwrap();
+        drop(sender);
+        encode(Output::Apng(filename.clone()), receiver, 120, 30, 4, 4).unwrap();
+
+        let decoder = png::Decoder::new(std::io::BufReader::new(std::fs::File::open(&filename).unwrap()));
+        let mut reader = decoder.read_info().unwrap();
+        assert_eq!(reader.info().animation_control().unwrap().num_frames, 2);
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        for _ in 0..2 {
+            reader.next_frame(&mut pixels).unwrap();
+        }
+    }
+}
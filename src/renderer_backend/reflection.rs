@@ -0,0 +1,379 @@
+use std::collections::BTreeMap;
+
+use wgpu::util::TextureFormatExt;
+
+use super::bind_group_layout::Layout;
+use super::error::PipelineError;
+use super::shader::Shader;
+
+// Bind group layouts and vertex inputs as the shader declares them.
+pub struct Reflection {
+    groups: BTreeMap<u32, Vec<wgpu::BindGroupLayoutEntry>>,
+    // The stages that actually use each (group, binding), layouts may be visible to more
+    used_stages: BTreeMap<(u32, u32), wgpu::ShaderStages>,
+    // Location and scalar kind of every vertex input, per vertex entry point
+    vertex_inputs: BTreeMap<String, Vec<(u32, naga::ScalarKind)>>,
+}
+
+impl Reflection {
+
+    // Visibility covers the stages of every entry point that uses a binding, see render_visibility.
+    pub fn new(shader: &Shader) -> Result<Self, PipelineError> {
+        let module = &shader.module;
+        let mut groups: BTreeMap<u32, Vec<wgpu::BindGroupLayoutEntry>> = BTreeMap::new();
+        let mut used_stages = BTreeMap::new();
+
+        for (handle, global) in module.global_variables.iter() {
+            let Some(binding) = &global.binding else {
+                continue;
+            };
+
+            let used = module.entry_points.iter()
+                .enumerate()
+                .filter(|(i, _)| !shader.info.get_entry_point(*i)[handle].is_empty())
+                .fold(wgpu::ShaderStages::NONE, |visibility, (_, entry_point)| visibility | stage(entry_point.stage));
+
+            let (ty, count) = binding_type(module, global.space, global.ty).map_err(|message| PipelineError::Binding {
+                group: binding.group,
+                binding: binding.binding,
+                message,
+            })?;
+
+            used_stages.insert((binding.group, binding.binding), used);
+            groups.entry(binding.group).or_default().push(wgpu::BindGroupLayoutEntry {
+                binding: binding.binding,
+                visibility: render_visibility(&ty, used),
+                ty,
+                count,
+            });
+        }
+
+        let vertex_inputs = module.entry_points.iter()
+            .filter(|entry_point| entry_point.stage == naga::ShaderStage::Vertex)
+            .map(|entry_point| (entry_point.name.clone(), vertex_inputs(module, &entry_point.function)))
+            .collect();
+
+        for entries in groups.values_mut() {
+            entries.sort_by_key(|entry| entry.binding);
+        }
+
+        Ok(Self { groups, used_stages, vertex_inputs })
+    }
+
+    // Empty for groups the shader doesn't use.
+    pub fn bind_group_layout_entries(&self, group: u32) -> &[wgpu::BindGroupLayoutEntry] {
+        self.groups.get(&group).map_or(&[], |entries| entries.as_slice())
+    }
+
+    #[allow(dead_code)]
+    pub fn bind_group_count(&self) -> u32 {
+        self.groups.keys().next_back().map_or(0, |group| group + 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn build_bind_group_layout(&self, device: &wgpu::Device, group: u32, label: &str) -> Layout {
+        Layout::new(device, self.bind_group_layout_entries(group), label)
+    }
+
+    // Checks hand written layout entries, e.g. bind_group_layout::Builder::entries, against the shader.
+    // Entries the shader doesn't use are fine, and so are declared bindings no entry point uses.
+    pub fn check_bind_group_layout(&self, group: u32, entries: &[wgpu::BindGroupLayoutEntry]) -> Result<(), PipelineError> {
+        for expected in self.bind_group_layout_entries(group) {
+            let error = |message: String| PipelineError::Binding { group, binding: expected.binding, message };
+            let used = self.used_stages[&(group, expected.binding)];
+            if used == wgpu::ShaderStages::NONE {
+                continue;
+            }
+
+            let entry = entries.iter()
+                .find(|entry| entry.binding == expected.binding)
+                .ok_or_else(|| error("the shader uses it but the layout has no entry for it".to_string()))?;
+
+            if !compatible(&expected.ty, &entry.ty) || expected.count != entry.count {
+                return Err(error(format!("the shader declares {:?} (count {:?}) but the layout has {:?} (count {:?})",
+                    expected.ty, expected.count, entry.ty, entry.count)));
+            }
+            if !entry.visibility.contains(used) {
+                return Err(error(format!("used in {:?} but the layout is only visible to {:?}",
+                    used, entry.visibility)));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Every input of the vertex entry point needs an attribute of the same scalar kind,
+    // the component counts may differ.
+    pub fn check_vertex_buffers(&self, vertex_entry: &str, layouts: &[wgpu::VertexBufferLayout]) -> Result<(), PipelineError> {
+        let Some(inputs) = self.vertex_inputs.get(vertex_entry) else {
+            // Let wgpu complain about the missing entry point
+            return Ok(());
+        };
+
+        for (location, kind) in inputs {
+            let error = |message: String| PipelineError::VertexInput { location: *location, message };
+
+            let attribute = layouts.iter()
+                .flat_map(|layout| layout.attributes.iter())
+                .find(|attribute| attribute.shader_location == *location)
+                .ok_or_else(|| error("no vertex buffer layout has an attribute for it".to_string()))?;
+
+            let attribute_kind = vertex_format_kind(attribute.format);
+            if attribute_kind != *kind {
+                return Err(error(format!("the shader reads {:?} but the attribute is {:?}", kind, attribute.format)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Bindings used by either render stage are visible to both, so moving a use between vs_main and
+// fs_main keeps the layout and hot reload doesn't need a restart. Vertex shaders can't write
+// storage, so writable storage stays with the stages that use it.
+fn render_visibility(ty: &wgpu::BindingType, used: wgpu::ShaderStages) -> wgpu::ShaderStages {
+    let writable = match ty {
+        wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Storage { read_only }, .. } => !read_only,
+        wgpu::BindingType::StorageTexture { access, .. } => *access != wgpu::StorageTextureAccess::ReadOnly,
+        _ => false,
+    };
+    if writable || !used.intersects(wgpu::ShaderStages::VERTEX_FRAGMENT) {
+        return used;
+    }
+
+    used | wgpu::ShaderStages::VERTEX_FRAGMENT
+}
+
+fn stage(stage: naga::ShaderStage) -> wgpu::ShaderStages {
+    match stage {
+        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+        naga::ShaderStage::Task => wgpu::ShaderStages::TASK,
+        naga::ShaderStage::Mesh => wgpu::ShaderStages::MESH,
+    }
+}
+
+fn binding_type(module: &naga::Module, space: naga::AddressSpace, ty: naga::Handle<naga::Type>)
+    -> Result<(wgpu::BindingType, Option<std::num::NonZeroU32>), String> {
+
+    let buffer = |ty| wgpu::BindingType::Buffer { ty, has_dynamic_offset: false, min_binding_size: None };
+
+    match (space, &module.types[ty].inner) {
+        (naga::AddressSpace::Uniform, _) => Ok((buffer(wgpu::BufferBindingType::Uniform), None)),
+        (naga::AddressSpace::Storage { access }, _) => {
+            let read_only = !access.contains(naga::StorageAccess::STORE);
+            Ok((buffer(wgpu::BufferBindingType::Storage { read_only }), None))
+        }
+        (naga::AddressSpace::Handle, naga::TypeInner::BindingArray { base, size }) => {
+            let count = match size {
+                naga::ArraySize::Constant(count) => *count,
+                _ => return Err("binding arrays need a constant size".to_string()),
+            };
+            let (ty, _) = binding_type(module, space, *base)?;
+            Ok((ty, Some(count)))
+        }
+        (naga::AddressSpace::Handle, naga::TypeInner::Sampler { comparison }) => {
+            let ty = if *comparison { wgpu::SamplerBindingType::Comparison } else { wgpu::SamplerBindingType::Filtering };
+            Ok((wgpu::BindingType::Sampler(ty), None))
+        }
+        (naga::AddressSpace::Handle, naga::TypeInner::Image { dim, arrayed, class }) => {
+            let view_dimension = match (dim, arrayed) {
+                (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+                (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+                (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+                (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+                (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+                (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+            };
+
+            let ty = match class {
+                naga::ImageClass::Sampled { kind, multi } => wgpu::BindingType::Texture {
+                    sample_type: match kind {
+                        naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                        naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                        // The shader can't tell, filterable is what add_material uses
+                        _ => wgpu::TextureSampleType::Float { filterable: !multi },
+                    },
+                    view_dimension,
+                    multisampled: *multi,
+                },
+                naga::ImageClass::Depth { multi } => wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension,
+                    multisampled: *multi,
+                },
+                naga::ImageClass::Storage { format, access } => wgpu::BindingType::StorageTexture {
+                    access: match (access.contains(naga::StorageAccess::LOAD), access.contains(naga::StorageAccess::STORE)) {
+                        (true, true) => wgpu::StorageTextureAccess::ReadWrite,
+                        (true, false) => wgpu::StorageTextureAccess::ReadOnly,
+                        _ => wgpu::StorageTextureAccess::WriteOnly,
+                    },
+                    format: wgpu::TextureFormat::from_storage_format(*format),
+                    view_dimension,
+                },
+                naga::ImageClass::External => return Err("external textures aren't supported".to_string()),
+            };
+            Ok((ty, None))
+        }
+        (space, inner) => Err(format!("can't derive a layout entry for {:?} in {:?}", inner, space)),
+    }
+}
+
+// Filterable and non-filtering variants work with both layouts, everything else has to be equal.
+fn compatible(shader: &wgpu::BindingType, layout: &wgpu::BindingType) -> bool {
+    use wgpu::BindingType::{Buffer, Sampler, Texture};
+
+    match (shader, layout) {
+        (Buffer { ty: a, .. }, Buffer { ty: b, .. }) => a == b,
+        (Sampler(wgpu::SamplerBindingType::Comparison), Sampler(b)) => *b == wgpu::SamplerBindingType::Comparison,
+        (Sampler(_), Sampler(b)) => *b != wgpu::SamplerBindingType::Comparison,
+        (Texture { sample_type: a, view_dimension: c, multisampled: e },
+            Texture { sample_type: b, view_dimension: d, multisampled: f }) => {
+            let same_sample_type = matches!((a, b), (wgpu::TextureSampleType::Float { .. }, wgpu::TextureSampleType::Float { .. })) || a == b;
+            same_sample_type && c == d && e == f
+        }
+        (a, b) => a == b,
+    }
+}
+
+fn vertex_inputs(module: &naga::Module, function: &naga::Function) -> Vec<(u32, naga::ScalarKind)> {
+    let mut inputs = Vec::new();
+    let mut add = |binding: &Option<naga::Binding>, ty: naga::Handle<naga::Type>| {
+        if let Some(naga::Binding::Location { location, .. }) = binding
+            && let Some(scalar) = module.types[ty].inner.scalar() {
+            inputs.push((*location, scalar.kind));
+        }
+    };
+
+    for argument in function.arguments.iter() {
+        match &module.types[argument.ty].inner {
+            naga::TypeInner::Struct { members, .. } if argument.binding.is_none() => {
+                for member in members {
+                    add(&member.binding, member.ty);
+                }
+            }
+            _ => add(&argument.binding, argument.ty),
+        }
+    }
+
+    inputs
+}
+
+fn vertex_format_kind(format: wgpu::VertexFormat) -> naga::ScalarKind {
+    use wgpu::VertexFormat as F;
+
+    match format {
+        F::Uint8 | F::Uint8x2 | F::Uint8x4 | F::Uint16 | F::Uint16x2 | F::Uint16x4 |
+        F::Uint32 | F::Uint32x2 | F::Uint32x3 | F::Uint32x4 => naga::ScalarKind::Uint,
+        F::Sint8 | F::Sint8x2 | F::Sint8x4 | F::Sint16 | F::Sint16x2 | F::Sint16x4 |
+        F::Sint32 | F::Sint32x2 | F::Sint32x3 | F::Sint32x4 => naga::ScalarKind::Sint,
+        _ => naga::ScalarKind::Float,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Reflection;
+    use crate::renderer_backend::{bindless, error::PipelineError, mesh_builder, shader};
+
+    fn demo_reflection() -> Reflection {
+        Reflection::new(&shader::parse("shaders/shader.wgsl", &[]).unwrap()).unwrap()
+    }
+
+    fn uniform(visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }
+    }
+
+    #[test]
+    fn derives_layouts_from_usage() {
+        let reflection = demo_reflection();
+        assert_eq!(reflection.bind_group_count(), 2);
+
+        let material = reflection.bind_group_layout_entries(0);
+        assert_eq!(material.len(), 2);
+        assert_eq!(material[0].visibility, wgpu::ShaderStages::VERTEX_FRAGMENT);
+        assert!(matches!(material[1].ty, wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)));
+
+        // Only vs_main reads it, fs_main may start to without changing the layout
+        assert_eq!(reflection.bind_group_layout_entries(1), &[uniform(wgpu::ShaderStages::VERTEX_FRAGMENT)]);
+    }
+
+    #[test]
+    fn immediates_replace_the_model_uniform() {
+        let shader = shader::parse("shaders/shader.wgsl", &["IMMEDIATES".to_string()]).unwrap();
+        let reflection = Reflection::new(&shader).unwrap();
+        assert_eq!(reflection.bind_group_count(), 1);
+        assert!(reflection.bind_group_layout_entries(1).is_empty());
+    }
+
+    #[test]
+    fn bindless_materials_are_a_binding_array() {
+        let shader = shader::parse("shaders/shader.wgsl", &["BINDLESS".to_string()]).unwrap();
+        let reflection = Reflection::new(&shader).unwrap();
+        let textures = &reflection.bind_group_layout_entries(0)[0];
+        assert_eq!(textures.count.map(|count| count.get() as usize), Some(bindless::MAX_MATERIALS));
+        assert_eq!(reflection.bind_group_layout_entries(1), &[uniform(wgpu::ShaderStages::VERTEX_FRAGMENT)]);
+    }
+
+    #[test]
+    fn reports_mismatched_bindings() {
+        let reflection = demo_reflection();
+        assert!(reflection.check_bind_group_layout(1, &[uniform(wgpu::ShaderStages::VERTEX_FRAGMENT)]).is_ok());
+
+        let error = reflection.check_bind_group_layout(1, &[uniform(wgpu::ShaderStages::FRAGMENT)]).unwrap_err();
+        assert!(matches!(error, PipelineError::Binding { group: 1, binding: 0, .. }), "{}", error);
+
+        let mut storage = uniform(wgpu::ShaderStages::VERTEX);
+        storage.ty = wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        };
+        let error = reflection.check_bind_group_layout(1, &[storage]).unwrap_err();
+        assert!(matches!(error, PipelineError::Binding { group: 1, binding: 0, .. }), "{}", error);
+
+        assert!(reflection.check_bind_group_layout(1, &[]).is_err());
+    }
+
+    #[test]
+    fn unused_bindings_need_no_entry() {
+        let directory = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("target/reflection");
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("unused.wgsl");
+        std::fs::write(&path, "@group(0) @binding(0) var<uniform> used: vec4<f32>;\n\
+            @group(0) @binding(1) var<uniform> unused: vec4<f32>;\n\
+            @fragment fn fs_main() -> @location(0) vec4<f32> { return used; }\n").unwrap();
+
+        let reflection = Reflection::new(&shader::parse(path.to_str().unwrap(), &[]).unwrap()).unwrap();
+        assert_eq!(reflection.bind_group_layout_entries(0).len(), 2);
+        reflection.check_bind_group_layout(0, &[uniform(wgpu::ShaderStages::FRAGMENT)]).unwrap();
+    }
+
+    #[test]
+    fn reports_mismatched_vertex_inputs() {
+        let reflection = demo_reflection();
+        assert!(reflection.check_vertex_buffers("vs_main", &[mesh_builder::Vertex::get_layout()]).is_ok());
+
+        let error = reflection.check_vertex_buffers("vs_main", &[]).unwrap_err();
+        assert!(matches!(error, PipelineError::VertexInput { location: 0, .. }), "{}", error);
+
+        let attributes = wgpu::vertex_attr_array![0 => Float32x3, 1 => Uint32x3];
+        let layout = wgpu::VertexBufferLayout {
+            array_stride: 24,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &attributes,
+        };
+        let error = reflection.check_vertex_buffers("vs_main", &[layout]).unwrap_err();
+        assert!(matches!(error, PipelineError::VertexInput { location: 1, .. }), "{}", error);
+    }
+}
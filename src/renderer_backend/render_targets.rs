@@ -0,0 +1,125 @@
+use super::pipeline::ColorTarget;
+
+#[allow(dead_code)]
+pub struct ColorBuffer {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub clear_color: wgpu::Color,
+    label: String,
+}
+
+#[allow(dead_code)]
+impl ColorBuffer {
+
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat, clear_color: wgpu::Color, label: &str) -> Self {
+
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            label: Some(label),
+            view_formats: &[],
+        };
+        let texture = device.create_texture(&texture_descriptor);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view, clear_color, label: label.to_string() }
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.texture.format()
+    }
+}
+
+// A set of same-sized color buffers written by one pass, e.g. a G-buffer or an object ID buffer
+// next to the color. Buffer i is bound to @location(i) of the fragment shader.
+#[allow(dead_code)]
+pub struct RenderTargets {
+    pub buffers: Vec<ColorBuffer>,
+    width: u32,
+    height: u32,
+}
+
+#[allow(dead_code)]
+impl RenderTargets {
+
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { buffers: Vec::new(), width, height }
+    }
+
+    pub fn add_buffer(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat, clear_color: wgpu::Color, label: &str) -> &mut Self {
+        self.buffers.push(ColorBuffer::new(device, self.width, self.height, format, clear_color, label));
+
+        self
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    // Pipeline targets matching the buffers, pass these to pipeline::Builder::set_color_targets
+    // and adjust blend modes or write masks where needed.
+    pub fn color_targets(&self) -> Vec<ColorTarget> {
+        self.buffers.iter()
+            .map(|buffer| ColorTarget::new(buffer.format()))
+            .collect()
+    }
+
+    // Clears every buffer to its clear color when the pass begins.
+    pub fn color_attachments(&self) -> Vec<Option<wgpu::RenderPassColorAttachment<'_>>> {
+        self.buffers.iter()
+            .map(|buffer| Some(wgpu::RenderPassColorAttachment {
+                view: &buffer.view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(buffer.clear_color),
+                    store: wgpu::StoreOp::Store,
+                },
+            }))
+            .collect()
+    }
+
+    // Recreates every buffer with the same format, clear color and label.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        for buffer in self.buffers.iter_mut() {
+            *buffer = ColorBuffer::new(device, width, height, buffer.format(), buffer.clear_color, &buffer.label);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RenderTargets;
+    use crate::renderer_backend::{pipeline, testing};
+
+    #[test]
+    fn render_targets_receive_their_own_outputs() {
+        let (device, queue) = testing::device();
+
+        let mut targets = RenderTargets::new(4, 4);
+        targets.add_buffer(&device, wgpu::TextureFormat::Rgba8Unorm, wgpu::Color::BLACK, "Color Buffer")
+            .add_buffer(&device, wgpu::TextureFormat::Rgba8Unorm, wgpu::Color::BLACK, "ID Buffer");
+
+        let pipeline = {
+            let mut builder = pipeline::Builder::new(&device);
+            builder.set_shader_module("shaders/mrt_test.wgsl", "vs_main", "fs_main")
+                .set_color_targets(&targets.color_targets());
+            builder.build("MRT Pipeline").unwrap()
+        };
+
+        let expected = [[255, 0, 0, 255], [0, 0, 255, 255]];
+        for (pixels, expected) in testing::render_fullscreen(&device, &queue, &pipeline, &targets).iter().zip(expected) {
+            assert!(pixels.chunks_exact(4).all(|pixel| pixel == expected));
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use super::bind_group_layout::Layout;
+
+// Hands out one shared bind group layout or sampler per distinct descriptor, so equal
+// layouts are the same object and pipelines built from them always accept the same bind groups.
+// Labels aren't part of the key, the first label asked for sticks.
+pub struct ResourceCache {
+    layouts: HashMap<Vec<wgpu::BindGroupLayoutEntry>, Layout>,
+    samplers: HashMap<SamplerKey, wgpu::Sampler>,
+}
+
+impl ResourceCache {
+
+    pub fn new() -> Self {
+        Self { layouts: HashMap::new(), samplers: HashMap::new() }
+    }
+
+    // Entry order doesn't matter, entries are keyed by binding.
+    pub fn bind_group_layout(&mut self, device: &wgpu::Device, entries: &[wgpu::BindGroupLayoutEntry], label: &str)
+        -> Layout {
+
+        let mut key = entries.to_vec();
+        key.sort_by_key(|entry| entry.binding);
+
+        self.layouts.entry(key)
+            .or_insert_with_key(|entries| Layout::new(device, entries, label))
+            .clone()
+    }
+
+    pub fn sampler(&mut self, device: &wgpu::Device, descriptor: &wgpu::SamplerDescriptor) -> wgpu::Sampler {
+        self.samplers.entry(SamplerKey::new(descriptor))
+            .or_insert_with(|| device.create_sampler(descriptor))
+            .clone()
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.layouts.len() + self.samplers.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Everything of a SamplerDescriptor but the label, with the f32 clamps as bits.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct SamplerKey {
+    address_modes: [wgpu::AddressMode; 3],
+    filters: (wgpu::FilterMode, wgpu::FilterMode, wgpu::MipmapFilterMode),
+    lod_clamp: (u32, u32),
+    compare: Option<wgpu::CompareFunction>,
+    anisotropy_clamp: u16,
+    border_color: Option<wgpu::SamplerBorderColor>,
+}
+
+impl SamplerKey {
+    fn new(descriptor: &wgpu::SamplerDescriptor) -> Self {
+        Self {
+            address_modes: [descriptor.address_mode_u, descriptor.address_mode_v, descriptor.address_mode_w],
+            filters: (descriptor.mag_filter, descriptor.min_filter, descriptor.mipmap_filter),
+            lod_clamp: (descriptor.lod_min_clamp.to_bits(), descriptor.lod_max_clamp.to_bits()),
+            compare: descriptor.compare,
+            anisotropy_clamp: descriptor.anisotropy_clamp,
+            border_color: descriptor.border_color,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ResourceCache, SamplerKey};
+    use crate::renderer_backend::{bind_group_layout, testing};
+
+    #[test]
+    fn sampler_keys_ignore_labels() {
+        let descriptor = wgpu::SamplerDescriptor { label: Some("A"), ..Default::default() };
+        let relabeled = wgpu::SamplerDescriptor { label: Some("B"), ..Default::default() };
+        let linear = wgpu::SamplerDescriptor { mag_filter: wgpu::FilterMode::Linear, ..Default::default() };
+
+        assert!(SamplerKey::new(&descriptor) == SamplerKey::new(&relabeled));
+        assert!(SamplerKey::new(&descriptor) != SamplerKey::new(&linear));
+    }
+
+    #[test]
+    fn equal_layouts_and_samplers_are_shared() {
+        let (device, _queue) = testing::device();
+        let mut resources = ResourceCache::new();
+
+        let mut builder = bind_group_layout::Builder::new(&device);
+        builder.add_material();
+        let layout = builder.build_cached(&mut resources, "Material Bind Group Layout");
+
+        // Same entries in a different order
+        let visibility = wgpu::ShaderStages::FRAGMENT;
+        builder.add_sampler(visibility, wgpu::SamplerBindingType::Filtering).set_binding(1)
+            .add_texture(visibility, wgpu::TextureSampleType::Float { filterable: true },
+                wgpu::TextureViewDimension::D2).set_binding(0);
+        let layout_again = builder.build_cached(&mut resources, "Material Bind Group Layout Again");
+        assert!(layout == layout_again);
+
+        let sampler = resources.sampler(&device, &wgpu::SamplerDescriptor { label: Some("A"), ..Default::default() });
+        let sampler_again = resources.sampler(&device, &wgpu::SamplerDescriptor { label: Some("B"), ..Default::default() });
+        assert!(sampler == sampler_again);
+        assert_eq!(resources.len(), 2);
+    }
+}
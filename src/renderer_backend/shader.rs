@@ -0,0 +1,63 @@
+use std::path::{Path, PathBuf};
+
+use super::error::{self, PipelineError};
+use super::preprocessor::{self, Output, SourceMap};
+
+// A preprocessed shader that naga parsed and validated.
+pub struct Shader {
+    pub label: String,
+    pub source_code: String,
+    pub module: naga::Module,
+    pub info: naga::valid::ModuleInfo,
+}
+
+// Runs the preprocessed WGSL through naga so errors come with a line and column in the original files.
+pub fn parse(shader_filename: &str, defines: &[String]) -> Result<Shader, PipelineError> {
+    let Output { code: source_code, source_map } = preprocessor::process(shader_filename, defines)?;
+    let path = source_map.files()[0].clone();
+
+    let module = naga::front::wgsl::parse_str(&source_code).map_err(|e| {
+        let (path, location) = original_location(&path, &source_map, e.location(&source_code));
+        PipelineError::Parse { path, location, message: e.message().to_string() }
+    })?;
+
+    // Device specific capabilities are left to wgpu, which knows the real ones
+    let mut validator = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all());
+    let info = validator.validate(&module).map_err(|e| {
+        let (path, location) = original_location(&path, &source_map, e.location(&source_code));
+        PipelineError::Validation { path, location, message: error::describe(e.as_inner()) }
+    })?;
+
+    Ok(Shader { label: shader_filename.to_string(), source_code, module, info })
+}
+
+impl Shader {
+    // Anything naga accepts but the device doesn't is caught with an error scope.
+    pub fn create_module(&self, device: &wgpu::Device) -> Result<wgpu::ShaderModule, PipelineError> {
+        let shader_module_descriptor = wgpu::ShaderModuleDescriptor {
+            label: Some(&self.label),
+            source: wgpu::ShaderSource::Wgsl(self.source_code.as_str().into()),
+        };
+        let error_scope = device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader_module = device.create_shader_module(shader_module_descriptor);
+
+        match pollster::block_on(error_scope.pop()) {
+            Some(e) => Err(PipelineError::Validation { path: PathBuf::from(&self.label), location: None, message: error::describe(&e) }),
+            None => Ok(shader_module),
+        }
+    }
+}
+
+fn original_location(path: &Path, source_map: &SourceMap, location: Option<naga::SourceLocation>)
+    -> (PathBuf, Option<error::SourceLocation>) {
+
+    let original = location.and_then(|location| {
+        let (file, line) = source_map.resolve(location.line_number)?;
+        Some((file.to_path_buf(), error::SourceLocation { line, column: location.line_position }))
+    });
+
+    match original {
+        Some((file, location)) => (file, Some(location)),
+        None => (path.to_path_buf(), location.map(Into::into)),
+    }
+}
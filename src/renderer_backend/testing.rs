@@ -0,0 +1,60 @@
+// Helpers for the unit tests that need a GPU. They run on the software adapter like the golden tests.
+
+use super::{adapter, readback::Readback, render_targets::RenderTargets};
+
+pub fn device() -> (wgpu::Device, wgpu::Queue) {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(), ..Default::default()
+    });
+
+    pollster::block_on(async {
+        let adapter = adapter::select(&instance, &adapter::Options::fallback(), None).await
+            .unwrap_or_else(|e| panic!("Can't get an adapter: {}", e));
+        let device_descriptor = wgpu::DeviceDescriptor {
+            label: Some("Test Device"),
+            ..Default::default()
+        };
+        adapter.request_device(&device_descriptor).await.unwrap()
+    })
+}
+
+// A 4x4 2D texture with a single layer and mip level, e.g. for checking bind groups.
+pub fn texture(device: &wgpu::Device, format: wgpu::TextureFormat, usage: wgpu::TextureUsages) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Test Texture"),
+        size: wgpu::Extent3d { width: 4, height: 4, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+    })
+}
+
+// Draws a single fullscreen triangle into every target and reads them back.
+pub fn render_fullscreen(device: &wgpu::Device, queue: &wgpu::Queue, pipeline: &wgpu::RenderPipeline,
+    targets: &RenderTargets) -> Vec<Vec<u8>> {
+
+    let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    {
+        let color_attachments = targets.color_attachments();
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("Fullscreen Pass"),
+            color_attachments: &color_attachments,
+            ..Default::default()
+        };
+        let mut renderpass = command_encoder.begin_render_pass(&render_pass_descriptor);
+        renderpass.set_pipeline(pipeline);
+        renderpass.draw(0..3, 0..1);
+    }
+    let (width, height) = targets.size();
+    let readbacks: Vec<Readback> = targets.buffers.iter().map(|buffer| {
+        let readback = Readback::new(device, width, height, buffer.format());
+        readback.copy_from(&mut command_encoder, &buffer.texture);
+        readback
+    }).collect();
+    queue.submit(std::iter::once(command_encoder.finish()));
+
+    readbacks.iter().map(|readback| readback.read(device).unwrap()).collect()
+}
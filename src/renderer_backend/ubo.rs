@@ -1,5 +1,6 @@
-use super::bind_group;
+use super::{bind_group, bind_group_layout::Layout, error::BindGroupError};
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct UBO {
     pub buffer: wgpu::Buffer,
     pub bind_groups: Vec<wgpu::BindGroup>,
@@ -8,11 +9,10 @@ pub struct UBO {
 
 impl UBO {
 
-    pub fn new(device: &wgpu::Device, object_count: usize, layout: wgpu::BindGroupLayout) -> Self {
+    // One `element_size` slot per object, each bound by its own bind group.
+    pub fn new(device: &wgpu::Device, object_count: usize, element_size: u64, layout: &Layout) -> Result<Self, BindGroupError> {
 
-        let alignment = core::cmp::max(
-            device.limits().min_storage_buffer_offset_alignment as u32,
-            std::mem::size_of::<glam::Mat4>() as u32) as u64;
+        let alignment = element_size.next_multiple_of(device.limits().min_uniform_buffer_offset_alignment as u64);
 
         let buffer_descriptor = wgpu::BufferDescriptor {
             label: Some("UBO"),
@@ -26,18 +26,17 @@ impl UBO {
         let mut bind_groups: Vec<wgpu::BindGroup> = Vec::new();
         for i in 0..object_count {
             let mut builder = bind_group::Builder::new(device);
-            builder.set_layout(&layout);
+            builder.set_layout(layout);
             builder.add_buffer(&buffer, i as u64 * alignment);
-            bind_groups.push(builder.build("Matrix"));
+            bind_groups.push(builder.build("Matrix")?);
         }
 
-        Self { buffer, bind_groups, alignment }
+        Ok(Self { buffer, bind_groups, alignment })
     }
 
-    pub fn upload(&mut self, i: u64, matrix: &glam::Mat4, queue: &wgpu::Queue) {
+    pub fn upload<T: bytemuck::Pod>(&mut self, i: u64, value: &T, queue: &wgpu::Queue) {
         let offset = i * self.alignment;
-        let data = bytemuck::cast_slice(matrix.as_ref());
-        queue.write_buffer(&self.buffer, offset, data);
+        queue.write_buffer(&self.buffer, offset, bytemuck::bytes_of(value));
 
     }
 }
\ No newline at end of file
@@ -0,0 +1,29 @@
+#ifdef BINDLESS
+// Every material's texture, ObjectData.material picks one. The size is bindless::MAX_MATERIALS.
+@group(0) @binding(0) var materialTextures: binding_array<texture_2d<f32>, 8>;
+#else
+@group(0) @binding(0) var myTexture: texture_2d<f32>;
+#endif
+@group(0) @binding(1) var mySampler: sampler;
+
+struct ObjectData {
+    model: mat4x4<f32>,
+    material: u32,
+};
+
+#ifdef IMMEDIATES
+var<immediate> object: ObjectData;
+#else
+@group(1) @binding(0) var<uniform> object: ObjectData;
+#endif
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+};
+
+struct VertexPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    @location(1) texCoord: vec2<f32>,
+};
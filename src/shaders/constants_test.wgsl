@@ -0,0 +1,15 @@
+// Fullscreen triangle in a color picked by override constants.
+
+override RED: f32 = 0.0;
+override USE_BLUE: bool = false;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(RED, 0.0, select(0.0, 1.0, USE_BLUE), 1.0);
+}
@@ -0,0 +1,21 @@
+// Uses one binding of every kind at scattered indices, for the bind group layout builder test.
+@group(0) @binding(0) var<uniform> scale: vec4<f32>;
+@group(0) @binding(2) var<storage, read> input: array<f32>;
+@group(0) @binding(3) var<storage, read_write> output: array<f32>;
+@group(0) @binding(5) var shadow_map: texture_depth_2d;
+@group(0) @binding(6) var shadow_sampler: sampler_comparison;
+@group(0) @binding(7) var sky: texture_cube<f32>;
+@group(0) @binding(8) var layers: texture_2d_array<f32>;
+@group(0) @binding(9) var volume: texture_3d<f32>;
+@group(0) @binding(10) var linear_sampler: sampler;
+@group(0) @binding(12) var image: texture_storage_2d<rgba8unorm, write>;
+
+@compute @workgroup_size(1)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let shadow = textureSampleCompareLevel(shadow_map, shadow_sampler, vec2<f32>(0.5), 0.5);
+    let color = textureSampleLevel(sky, linear_sampler, vec3<f32>(1.0, 0.0, 0.0), 0.0)
+        + textureSampleLevel(layers, linear_sampler, vec2<f32>(0.5), 0, 0.0)
+        + textureSampleLevel(volume, linear_sampler, vec3<f32>(0.5), 0.0);
+    output[id.x] = input[id.x] * scale.x * shadow;
+    textureStore(image, vec2<i32>(id.xy), color);
+}
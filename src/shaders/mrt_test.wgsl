@@ -0,0 +1,20 @@
+// Fullscreen triangle writing a different constant to each of two color targets.
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+struct Targets {
+    @location(0) color: vec4<f32>,
+    @location(1) id: vec4<f32>,
+};
+
+@fragment
+fn fs_main() -> Targets {
+    var out: Targets;
+    out.color = vec4<f32>(1.0, 0.0, 0.0, 1.0);
+    out.id = vec4<f32>(0.0, 0.0, 1.0, 1.0);
+    return out;
+}
@@ -1,23 +1,10 @@
-@group(0) @binding(0) var myTexture: texture_2d<f32>;
-@group(0) @binding(1) var mySampler: sampler;
-@group(1) @binding(0) var<uniform> model: mat4x4<f32>;
-
-struct Vertex {
-    @location(0) position: vec3<f32>,
-    @location(1) color: vec3<f32>,
-};
-
-struct VertexPayload {
-    @builtin(position) position: vec4<f32>,
-    @location(0) color: vec3<f32>,
-    @location(1) texCoord: vec2<f32>,
-};
+#include "common.wgsl"
 
 @vertex
 fn vs_main(vertex: Vertex) -> VertexPayload {
 
     var out: VertexPayload;
-    out.position = model * vec4<f32>(vertex.position, 1.0);
+    out.position = object.model * vec4<f32>(vertex.position, 1.0);
     out.color = vertex.color;
     out.texCoord = vec2<f32>(0.5 * (vertex.position.x + 1f), -0.5 * (vertex.position.y + 1f));
     return out;
@@ -25,5 +12,11 @@ fn vs_main(vertex: Vertex) -> VertexPayload {
 
 @fragment
 fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color, 1.0) * textureSample(myTexture, mySampler, in.texCoord);
+#ifdef BINDLESS
+    // The index comes from per-draw data, so it's uniform across the draw
+    let color = textureSample(materialTextures[object.material], mySampler, in.texCoord);
+#else
+    let color = textureSample(myTexture, mySampler, in.texCoord);
+#endif
+    return vec4<f32>(in.color, 1.0) * color;
 }
\ No newline at end of file